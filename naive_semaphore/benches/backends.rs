@@ -0,0 +1,48 @@
+//! Acquire/release throughput of `NaiveSemaphore` under contention.
+//!
+//! The `Mutex`/`Condvar` backend is picked at compile time, so compare them via baselines:
+//!
+//! ```text
+//! cargo bench --bench backends -- --save-baseline std
+//! cargo bench --bench backends --features parking_lot -- --baseline std
+//! ```
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use naive_semaphore::NaiveSemaphore;
+
+const ROUNDS_PER_THREAD: usize = 1_000;
+
+fn contended_rounds(semaphore: &NaiveSemaphore, threads: usize) {
+    std::thread::scope(|s| {
+        for _ in 0..threads {
+            s.spawn(|| {
+                for _ in 0..ROUNDS_PER_THREAD {
+                    let _permit = semaphore.wait();
+                }
+            });
+        }
+    });
+}
+
+fn bench_contended(c: &mut Criterion) {
+    let backend = if cfg!(feature = "parking_lot") {
+        "parking_lot"
+    } else {
+        "std"
+    };
+    let mut group = c.benchmark_group(format!("contended/{}", backend));
+
+    for threads in [2, 4, 8] {
+        let semaphore = NaiveSemaphore::new(2);
+        group.throughput(Throughput::Elements((threads * ROUNDS_PER_THREAD) as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(threads),
+            &threads,
+            |b, &threads| b.iter(|| contended_rounds(&semaphore, threads)),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_contended);
+criterion_main!(benches);
@@ -0,0 +1,52 @@
+//! Uncontended acquire/release of `NaiveSemaphore`, which takes neither the lock nor the heap.
+//!
+//! A counting global allocator checks the latter before measuring, the lock is covered by
+//! `test_uncontended_wait_takes_no_lock`.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use naive_semaphore::NaiveSemaphore;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+fn assert_allocation_free(semaphore: &NaiveSemaphore) {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    for _ in 0..1_000 {
+        drop(black_box(semaphore.wait()));
+        drop(black_box(semaphore.try_wait()));
+    }
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+    assert_eq!(allocations, 0, "the uncontended fast path allocated");
+}
+
+fn bench_uncontended(c: &mut Criterion) {
+    let semaphore = NaiveSemaphore::new(1);
+    assert_allocation_free(&semaphore);
+
+    let mut group = c.benchmark_group("uncontended");
+    group.bench_function("wait", |b| b.iter(|| drop(black_box(semaphore.wait()))));
+    group.bench_function("try_wait", |b| {
+        b.iter(|| drop(black_box(semaphore.try_wait())))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_uncontended);
+criterion_main!(benches);
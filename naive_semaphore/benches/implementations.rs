@@ -0,0 +1,146 @@
+//! Acquire/release throughput and tail latency of the semaphores in this crate, side by side.
+//!
+//! The uncontended and contended scenarios are reported as separate groups. Criterion only
+//! reports throughput, so the percentiles of single acquisitions under contention are
+//! printed to stderr before each contended group.
+//!
+//! ```text
+//! cargo bench --bench implementations
+//! ```
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use naive_semaphore::{FairSemaphore, NaiveSemaphore, SpinSemaphore};
+use std::time::{Duration, Instant};
+
+const ROUNDS_PER_THREAD: usize = 1_000;
+
+/// One acquire/release cycle on any of the semaphores.
+trait Cycle: Sync {
+    const NAME: &'static str;
+
+    fn new(max: usize) -> Self;
+
+    fn cycle(&self);
+}
+
+impl Cycle for NaiveSemaphore {
+    const NAME: &'static str = "naive";
+
+    fn new(max: usize) -> Self {
+        NaiveSemaphore::new(max)
+    }
+
+    fn cycle(&self) {
+        drop(self.wait());
+    }
+}
+
+impl Cycle for FairSemaphore {
+    const NAME: &'static str = "fair";
+
+    fn new(max: usize) -> Self {
+        FairSemaphore::new(max)
+    }
+
+    fn cycle(&self) {
+        drop(self.wait());
+    }
+}
+
+impl Cycle for SpinSemaphore {
+    const NAME: &'static str = "spin";
+
+    fn new(max: usize) -> Self {
+        SpinSemaphore::new(max)
+    }
+
+    fn cycle(&self) {
+        drop(self.wait());
+    }
+}
+
+fn contended_rounds<S: Cycle>(semaphore: &S, threads: usize) {
+    std::thread::scope(|s| {
+        for _ in 0..threads {
+            s.spawn(|| {
+                for _ in 0..ROUNDS_PER_THREAD {
+                    semaphore.cycle();
+                }
+            });
+        }
+    });
+}
+
+/// The latencies of all single cycles of one contended run, sorted.
+fn contended_latencies<S: Cycle>(semaphore: &S, threads: usize) -> Vec<Duration> {
+    let mut latencies: Vec<Duration> = std::thread::scope(|s| {
+        let handles: Vec<_> = (0..threads)
+            .map(|_| {
+                s.spawn(|| {
+                    (0..ROUNDS_PER_THREAD)
+                        .map(|_| {
+                            let start = Instant::now();
+                            semaphore.cycle();
+                            start.elapsed()
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect()
+    });
+    latencies.sort();
+    latencies
+}
+
+fn percentile(sorted: &[Duration], pct: usize) -> Duration {
+    sorted[(sorted.len() - 1) * pct / 100]
+}
+
+fn bench_uncontended<S: Cycle>(c: &mut Criterion) {
+    let semaphore = S::new(1);
+    c.benchmark_group("uncontended")
+        .bench_function(S::NAME, |b| b.iter(|| semaphore.cycle()));
+}
+
+fn bench_contended<S: Cycle>(c: &mut Criterion) {
+    for max in [1, 4] {
+        let mut group = c.benchmark_group(format!("contended/{}/max={}", S::NAME, max));
+        for threads in [2, 8] {
+            let semaphore = S::new(max);
+
+            let latencies = contended_latencies(&semaphore, threads);
+            eprintln!(
+                "{}/max={}/threads={}: p50 {:?}, p99 {:?}, max {:?}",
+                S::NAME,
+                max,
+                threads,
+                percentile(&latencies, 50),
+                percentile(&latencies, 99),
+                latencies[latencies.len() - 1]
+            );
+
+            group.throughput(Throughput::Elements((threads * ROUNDS_PER_THREAD) as u64));
+            group.bench_with_input(
+                BenchmarkId::from_parameter(threads),
+                &threads,
+                |b, &threads| b.iter(|| contended_rounds(&semaphore, threads)),
+            );
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(
+    benches,
+    bench_uncontended::<NaiveSemaphore>,
+    bench_uncontended::<FairSemaphore>,
+    bench_uncontended::<SpinSemaphore>,
+    bench_contended::<NaiveSemaphore>,
+    bench_contended::<FairSemaphore>,
+    bench_contended::<SpinSemaphore>,
+);
+criterion_main!(benches);
@@ -0,0 +1,48 @@
+//! Many threads holding the permits for brief bursts only, waking one parked thread per
+//! freed permit compared to waking all of them with `NaiveSemaphore::with_broadcast_on_release`.
+//!
+//! ```text
+//! cargo bench --bench notify
+//! ```
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use naive_semaphore::NaiveSemaphore;
+
+const ROUNDS_PER_THREAD: usize = 500;
+
+/// Every thread takes a permit, does a brief burst of work and releases it again.
+fn bursts(semaphore: &NaiveSemaphore, threads: usize) {
+    std::thread::scope(|s| {
+        for _ in 0..threads {
+            s.spawn(|| {
+                for _ in 0..ROUNDS_PER_THREAD {
+                    let _permit = semaphore.wait();
+                    for _ in 0..64 {
+                        std::hint::spin_loop();
+                    }
+                }
+            });
+        }
+    });
+}
+
+fn bench_notify(c: &mut Criterion) {
+    for threads in [8, 32] {
+        let mut group = c.benchmark_group(format!("bursts/threads={}", threads));
+        group.throughput(Throughput::Elements((threads * ROUNDS_PER_THREAD) as u64));
+        for (name, semaphore) in [
+            ("notify_one", NaiveSemaphore::new(2)),
+            ("broadcast", NaiveSemaphore::with_broadcast_on_release(2)),
+        ] {
+            group.bench_with_input(
+                BenchmarkId::from_parameter(name),
+                &semaphore,
+                |b, semaphore| b.iter(|| bursts(semaphore, threads)),
+            );
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, bench_notify);
+criterion_main!(benches);
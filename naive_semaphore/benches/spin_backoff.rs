@@ -0,0 +1,45 @@
+//! Waiting for permits held only for a short critical section, parking right away compared
+//! to spinning first with `NaiveSemaphore::with_spin`.
+//!
+//! ```text
+//! cargo bench --bench spin_backoff
+//! ```
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use naive_semaphore::NaiveSemaphore;
+
+const THREADS: usize = 4;
+const ROUNDS_PER_THREAD: usize = 1_000;
+
+/// Every thread takes the permit, does a few instructions of work and releases it again.
+fn short_critical_sections(semaphore: &NaiveSemaphore) {
+    std::thread::scope(|s| {
+        for _ in 0..THREADS {
+            s.spawn(|| {
+                for _ in 0..ROUNDS_PER_THREAD {
+                    let _permit = semaphore.wait();
+                    for _ in 0..16 {
+                        std::hint::spin_loop();
+                    }
+                }
+            });
+        }
+    });
+}
+
+fn bench_spin_backoff(c: &mut Criterion) {
+    let mut group = c.benchmark_group("short_critical_sections");
+    group.throughput(Throughput::Elements((THREADS * ROUNDS_PER_THREAD) as u64));
+    for spins in [0, 8, 32] {
+        let semaphore = NaiveSemaphore::with_spin(1, spins);
+        group.bench_with_input(
+            BenchmarkId::new("spins", spins),
+            &semaphore,
+            |b, semaphore| b.iter(|| short_critical_sections(semaphore)),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_spin_backoff);
+criterion_main!(benches);
@@ -0,0 +1,363 @@
+use crate::error::Timeout;
+use crate::naive_semaphore::{NaiveSemaphore, SemaphorePermit};
+use crate::timer::TimerEntry;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll, Waker};
+use std::time::Instant;
+
+/// The tasks parked in `Acquire` futures, woken in registration order as permits free up.
+#[derive(Default)]
+pub(crate) struct WakerList {
+    next_id: u64,
+    wakers: VecDeque<(u64, Waker)>,
+}
+
+impl WakerList {
+    /// Register 'waker' under 'id', or replace the waker already registered under it.
+    /// Returns whether the task was added to the list.
+    pub fn register(&mut self, id: &mut Option<u64>, waker: &Waker) -> bool {
+        if let Some(registered) = id.and_then(|id| self.wakers.iter_mut().find(|(i, _)| *i == id)) {
+            if !registered.1.will_wake(waker) {
+                registered.1 = waker.clone();
+            }
+            return false;
+        }
+        let new_id = self.next_id;
+        self.next_id += 1;
+        self.wakers.push_back((new_id, waker.clone()));
+        *id = Some(new_id);
+        true
+    }
+
+    /// Forget the waker registered under 'id', if it was not woken already.
+    /// Returns whether the task was removed from the list.
+    pub fn deregister(&mut self, id: &mut Option<u64>) -> bool {
+        let Some(id) = id.take() else {
+            return false;
+        };
+        let len = self.wakers.len();
+        self.wakers.retain(|(i, _)| *i != id);
+        self.wakers.len() < len
+    }
+
+    /// Wake up to 'n' tasks, the longest waiting first. Returns the count of tasks woken.
+    pub fn wake(&mut self, n: usize) -> usize {
+        let mut woken = 0;
+        while woken < n {
+            match self.wakers.pop_front() {
+                Some((_, waker)) => waker.wake(),
+                None => break,
+            }
+            woken += 1;
+        }
+        woken
+    }
+}
+
+/// The registration of one acquisition polled with `NaiveSemaphore::poll_acquire`,
+/// to be passed to every poll of it. Start each acquisition with a new one.
+#[derive(Debug, Default)]
+pub struct AcquireState {
+    // The registration in the semaphore's `WakerList` while pending.
+    pub(crate) id: Option<u64>,
+    // When the first poll found no free permit.
+    pub(crate) since: Option<Instant>,
+}
+
+/// The future returned by `NaiveSemaphore::acquire`, resolving to a permit.
+///
+/// Dropping it before completion is cancellation-safe: no permit is consumed, and its task
+/// is removed from the semaphore's wake list.
+#[must_use = "futures do nothing unless polled"]
+pub struct Acquire<'a> {
+    semaphore: &'a NaiveSemaphore,
+    state: AcquireState,
+}
+
+impl<'a> Acquire<'a> {
+    pub(crate) fn new(semaphore: &'a NaiveSemaphore) -> Self {
+        Self {
+            semaphore,
+            state: AcquireState::default(),
+        }
+    }
+}
+
+impl<'a> Future for Acquire<'a> {
+    type Output = SemaphorePermit<'a>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        this.semaphore.poll_acquire(cx, &mut this.state)
+    }
+}
+
+impl Drop for Acquire<'_> {
+    fn drop(&mut self) {
+        self.semaphore.cancel_acquire(&mut self.state);
+    }
+}
+
+/// The future returned by `NaiveSemaphore::acquire_timeout`, resolving to a permit or to
+/// `Timeout` once the deadline passed.
+///
+/// It is woken at the deadline by a timer thread of this crate, so it works on any executor.
+/// Resolving or dropping it cancels the timer and, like `Acquire`, consumes no permit unless
+/// it resolved to one.
+#[must_use = "futures do nothing unless polled"]
+pub struct AcquireTimeout<'a> {
+    semaphore: &'a NaiveSemaphore,
+    state: AcquireState,
+    deadline: Instant,
+    timer: Option<TimerEntry>,
+}
+
+impl<'a> AcquireTimeout<'a> {
+    pub(crate) fn new(semaphore: &'a NaiveSemaphore, deadline: Instant) -> Self {
+        Self {
+            semaphore,
+            state: AcquireState::default(),
+            deadline,
+            timer: None,
+        }
+    }
+}
+
+impl<'a> Future for AcquireTimeout<'a> {
+    type Output = Result<SemaphorePermit<'a>, Timeout>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        if let Poll::Ready(permit) = this.semaphore.poll_acquire(cx, &mut this.state) {
+            this.timer = None;
+            return Poll::Ready(Ok(permit));
+        }
+        if Instant::now() >= this.deadline {
+            this.timer = None;
+            this.semaphore.acquire_timed_out(&mut this.state);
+            return Poll::Ready(Err(Timeout));
+        }
+        match &this.timer {
+            Some(timer) => timer.update(cx.waker()),
+            None => this.timer = Some(TimerEntry::register(this.deadline, cx.waker())),
+        }
+        Poll::Pending
+    }
+}
+
+impl Drop for AcquireTimeout<'_> {
+    fn drop(&mut self) {
+        self.semaphore.cancel_acquire(&mut self.state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::task::Wake;
+    use std::time::Duration;
+
+    /// Counts how often it was woken.
+    #[derive(Default)]
+    struct CountingWaker(AtomicUsize);
+
+    impl CountingWaker {
+        fn wakeups(&self) -> usize {
+            self.0.load(Ordering::SeqCst)
+        }
+    }
+
+    impl Wake for CountingWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn test_poll_acquire() {
+        let semaphore = NaiveSemaphore::new(1);
+        let held = semaphore.wait();
+
+        let first = Arc::new(CountingWaker::default());
+        let second = Arc::new(CountingWaker::default());
+        let mut state = AcquireState::default();
+        let first_waker = Waker::from(Arc::clone(&first));
+        let second_waker = Waker::from(Arc::clone(&second));
+        let mut cx = Context::from_waker(&first_waker);
+        assert!(semaphore.poll_acquire(&mut cx, &mut state).is_pending());
+        // the task moved, only the waker of the latest poll is woken
+        let mut cx = Context::from_waker(&second_waker);
+        assert!(semaphore.poll_acquire(&mut cx, &mut state).is_pending());
+        assert_eq!(semaphore.waiting_count(), 1);
+
+        drop(held);
+        assert_eq!((first.wakeups(), second.wakeups()), (0, 1));
+        let permit = match semaphore.poll_acquire(&mut cx, &mut state) {
+            Poll::Ready(permit) => permit,
+            Poll::Pending => panic!("woken without a free permit"),
+        };
+        assert_eq!(semaphore.waiting_count(), 0);
+        assert_eq!(semaphore.current_count(), 1);
+        drop(permit);
+    }
+
+    #[test]
+    fn test_cancel_acquire() {
+        let semaphore = NaiveSemaphore::new(1);
+        let held = semaphore.wait();
+
+        let waker = Arc::new(CountingWaker::default());
+        let mut state = AcquireState::default();
+        let task_waker = Waker::from(Arc::clone(&waker));
+        let mut cx = Context::from_waker(&task_waker);
+        assert!(semaphore.poll_acquire(&mut cx, &mut state).is_pending());
+        semaphore.cancel_acquire(&mut state);
+        assert_eq!(semaphore.waiting_count(), 0);
+
+        drop(held);
+        assert_eq!(waker.wakeups(), 0);
+        assert_eq!(semaphore.current_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_acquire() {
+        let max = 2;
+        let semaphore = Arc::new(NaiveSemaphore::new(max));
+        let mut held = Vec::new();
+        for _ in 0..max {
+            held.push(semaphore.acquire().await);
+        }
+        assert_eq!(semaphore.current_count(), max);
+
+        let sem = Arc::clone(&semaphore);
+        let pending = tokio::spawn(async move {
+            let _permit = sem.acquire().await;
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!pending.is_finished());
+
+        held.pop();
+        tokio::time::timeout(Duration::from_secs(5), pending)
+            .await
+            .expect("the pending acquire did not complete after a release")
+            .unwrap();
+        drop(held);
+        assert_eq!(semaphore.current_count(), 0);
+    }
+
+    #[test]
+    fn test_cancelled_wakeup_is_passed_on() {
+        let semaphore = NaiveSemaphore::new(1);
+        let held = semaphore.wait();
+
+        let wakers: Vec<_> = (0..2).map(|_| Arc::new(CountingWaker::default())).collect();
+        let mut states: Vec<_> = wakers
+            .iter()
+            .map(|waker| {
+                let task_waker = Waker::from(Arc::clone(waker));
+                let mut state = AcquireState::default();
+                let mut cx = Context::from_waker(&task_waker);
+                assert!(semaphore.poll_acquire(&mut cx, &mut state).is_pending());
+                state
+            })
+            .collect();
+
+        // the first task is woken by the release, but gives up before polling again
+        drop(held);
+        assert_eq!((wakers[0].wakeups(), wakers[1].wakeups()), (1, 0));
+        semaphore.cancel_acquire(&mut states[0]);
+        assert_eq!(wakers[1].wakeups(), 1);
+
+        let task_waker = Waker::from(Arc::clone(&wakers[1]));
+        let mut cx = Context::from_waker(&task_waker);
+        assert!(semaphore.poll_acquire(&mut cx, &mut states[1]).is_ready());
+        assert_eq!(semaphore.current_count(), 0);
+        assert_eq!(semaphore.waiting_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_dropping_pending_acquires_loses_no_permit() {
+        let max = 4;
+        let semaphore = Arc::new(NaiveSemaphore::new(max));
+        let held: Vec<_> = (0..max).map(|_| semaphore.wait()).collect();
+
+        let tasks: Vec<_> = (0..16)
+            .map(|_| {
+                let semaphore = Arc::clone(&semaphore);
+                tokio::spawn(async move {
+                    let _permit = semaphore.acquire().await;
+                    tokio::task::yield_now().await;
+                })
+            })
+            .collect();
+        while semaphore.waiting_count() < 16 {
+            tokio::task::yield_now().await;
+        }
+
+        // drop every other future while it is pending
+        let (cancelled, surviving): (Vec<_>, Vec<_>) = tasks
+            .into_iter()
+            .enumerate()
+            .partition(|(idx, _)| idx % 2 == 0);
+        for (_, task) in &cancelled {
+            task.abort();
+        }
+        drop(held);
+
+        for (_, task) in surviving {
+            tokio::time::timeout(Duration::from_secs(5), task)
+                .await
+                .expect("a surviving acquire never completed")
+                .unwrap();
+        }
+        for (_, task) in cancelled {
+            assert!(task.await.unwrap_err().is_cancelled());
+        }
+        assert_eq!(semaphore.current_count(), 0);
+        assert_eq!(semaphore.waiting_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_acquire_timeout() {
+        let semaphore = Arc::new(NaiveSemaphore::new(1));
+        let held = semaphore.acquire_owned();
+
+        // no tokio timer involved, the future is woken by the crate's own
+        let result = semaphore.acquire_timeout(Duration::from_millis(20)).await;
+        assert!(matches!(result, Err(Timeout)));
+        assert_eq!(semaphore.waiting_count(), 0);
+
+        let holder = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            drop(held);
+        });
+        let permit = semaphore
+            .acquire_timeout(Duration::from_secs(5))
+            .await
+            .expect("the acquire timed out after a release");
+        assert_eq!(semaphore.current_count(), 1);
+        drop(permit);
+        holder.join().unwrap();
+        assert_eq!(semaphore.current_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_dropped_acquire_consumes_nothing() {
+        let semaphore = NaiveSemaphore::new(1);
+        let held = semaphore.wait();
+
+        let pending = semaphore.acquire();
+        assert!(tokio::time::timeout(Duration::from_millis(10), pending)
+            .await
+            .is_err());
+        assert!(semaphore.wakers.lock().wakers.is_empty());
+
+        drop(held);
+        assert_eq!(semaphore.current_count(), 0);
+        assert!(semaphore.try_wait().is_some());
+    }
+}
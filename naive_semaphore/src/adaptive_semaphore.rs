@@ -0,0 +1,208 @@
+use crate::naive_semaphore::{NaiveSemaphore, SemaphorePermit};
+use crate::sync::Mutex;
+use std::time::Duration;
+
+/// The state of the control loop, updated on every release.
+#[derive(Default)]
+struct Control {
+    // Fast releases with waiters queuing since the capacity last grew.
+    fast: usize,
+    // Releases of any kind since the capacity last shrank.
+    since_shrink: usize,
+}
+
+/// A `NaiveSemaphore` adjusting its own capacity to the observed hold times, like AIMD
+/// congestion control: while permits are held for at most 'target' and threads queue up, the
+/// capacity grows by one per full round of permits; once a permit is held for longer, which
+/// points to a saturated resource behind them, the capacity halves, at most once per round.
+/// The capacity always stays within 'min_max..=max_max'.
+///
+/// ```
+/// use naive_semaphore::AdaptiveSemaphore;
+/// use std::time::Duration;
+///
+/// let semaphore = AdaptiveSemaphore::new(1, 16, Duration::from_millis(50));
+/// assert_eq!(semaphore.capacity(), 1);
+/// drop(semaphore.wait());
+/// ```
+pub struct AdaptiveSemaphore {
+    semaphore: NaiveSemaphore,
+    min_max: usize,
+    max_max: usize,
+    target: Duration,
+    control: Mutex<Control>,
+}
+
+impl AdaptiveSemaphore {
+    /// Create a semaphore starting with a capacity of 'min_max', adjusted between 'min_max'
+    /// and 'max_max' depending on whether permits are held for longer than 'target'.
+    ///
+    /// # Panics
+    ///
+    /// Panics if 'min_max' is zero or exceeds 'max_max'.
+    pub fn new(min_max: usize, max_max: usize, target: Duration) -> Self {
+        assert!(
+            min_max <= max_max,
+            "the capacity bounds {}..={} are empty",
+            min_max,
+            max_max
+        );
+        Self {
+            semaphore: NaiveSemaphore::new(min_max),
+            min_max,
+            max_max,
+            target,
+            control: Mutex::new(Control::default()),
+        }
+    }
+
+    /// The current capacity, i.e. the count of threads admitted at most right now.
+    pub fn capacity(&self) -> usize {
+        self.semaphore.max_permits()
+    }
+
+    /// The count of currently running threads.
+    pub fn current_count(&self) -> usize {
+        self.semaphore.current_count()
+    }
+
+    /// Block a thread until a permit is free. How long the returned permit is held until it
+    /// is dropped feeds into the capacity.
+    pub fn wait(&self) -> AdaptivePermit<'_> {
+        AdaptivePermit {
+            semaphore: self,
+            permit: self.semaphore.wait(),
+        }
+    }
+
+    /// Adjust the capacity to a permit just held for 'held', while threads were 'queuing'
+    /// for a permit or not.
+    fn observe_hold(&self, held: Duration, queuing: bool) {
+        let mut control = self.control.lock();
+        let capacity = self.capacity();
+        control.since_shrink += 1;
+        if held > self.target {
+            control.fast = 0;
+            // one shrink per round, the other slow holds of the round saw the old capacity
+            if control.since_shrink >= capacity && capacity > self.min_max {
+                control.since_shrink = 0;
+                self.semaphore.set_max((capacity / 2).max(self.min_max));
+            }
+        } else if queuing {
+            control.fast += 1;
+            if control.fast >= capacity && capacity < self.max_max {
+                control.fast = 0;
+                self.semaphore.set_max(capacity + 1);
+            }
+        }
+    }
+}
+
+/// A permit acquired from an `AdaptiveSemaphore`, released when dropped (also on panic).
+#[must_use = "the permit is released immediately if it is not held"]
+pub struct AdaptivePermit<'a> {
+    semaphore: &'a AdaptiveSemaphore,
+    permit: SemaphorePermit<'a>,
+}
+
+impl Drop for AdaptivePermit<'_> {
+    fn drop(&mut self) {
+        // adjusted before the permit itself is released right after when dropping the field,
+        // timed from the admission, the time spent queuing before does not count
+        let queuing = self.semaphore.semaphore.waiting_count() > 0;
+        self.semaphore
+            .observe_hold(self.permit.held_duration(), queuing);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TARGET: Duration = Duration::from_millis(10);
+    const FAST: Duration = Duration::from_millis(1);
+    const SLOW: Duration = Duration::from_millis(100);
+
+    /// Observe 'holds' holds of 'held' each, with threads queuing or not.
+    fn observe(semaphore: &AdaptiveSemaphore, holds: usize, held: Duration, queuing: bool) {
+        for _ in 0..holds {
+            semaphore.observe_hold(held, queuing);
+        }
+    }
+
+    #[test]
+    fn test_fast_holds_grow_by_one_per_round() {
+        let semaphore = AdaptiveSemaphore::new(1, 3, TARGET);
+        // nobody queues, so there is no point in admitting more
+        observe(&semaphore, 5, FAST, false);
+        assert_eq!(semaphore.capacity(), 1);
+
+        observe(&semaphore, 1, FAST, true);
+        assert_eq!(semaphore.capacity(), 2);
+        // a full round of the new capacity for the next step
+        observe(&semaphore, 1, TARGET, true);
+        assert_eq!(semaphore.capacity(), 2);
+        observe(&semaphore, 1, TARGET, true);
+        assert_eq!(semaphore.capacity(), 3);
+
+        // clamped to 'max_max'
+        observe(&semaphore, 10, FAST, true);
+        assert_eq!(semaphore.capacity(), 3);
+    }
+
+    #[test]
+    fn test_slow_holds_halve_once_per_round() {
+        let semaphore = AdaptiveSemaphore::new(1, 8, TARGET);
+        observe(&semaphore, 1 + 2 + 3, FAST, true);
+        assert_eq!(semaphore.capacity(), 4);
+
+        observe(&semaphore, 1, SLOW, true);
+        assert_eq!(semaphore.capacity(), 2);
+        // the next slow hold still saw the old capacity
+        observe(&semaphore, 1, SLOW, true);
+        assert_eq!(semaphore.capacity(), 2);
+        observe(&semaphore, 1, SLOW, false);
+        assert_eq!(semaphore.capacity(), 1);
+
+        // clamped to 'min_max'
+        observe(&semaphore, 10, SLOW, true);
+        assert_eq!(semaphore.capacity(), 1);
+    }
+
+    #[test]
+    fn test_shrink_clamped_to_min() {
+        let semaphore = AdaptiveSemaphore::new(3, 8, TARGET);
+        observe(&semaphore, 3, FAST, true);
+        assert_eq!(semaphore.capacity(), 4);
+        observe(&semaphore, 1, SLOW, true);
+        assert_eq!(semaphore.capacity(), 3);
+    }
+
+    #[test]
+    fn test_slow_hold_restarts_the_fast_round() {
+        let semaphore = AdaptiveSemaphore::new(2, 8, TARGET);
+        observe(&semaphore, 1, FAST, true);
+        observe(&semaphore, 1, SLOW, true);
+        observe(&semaphore, 1, FAST, true);
+        assert_eq!(semaphore.capacity(), 2);
+        observe(&semaphore, 1, FAST, true);
+        assert_eq!(semaphore.capacity(), 3);
+    }
+
+    #[test]
+    fn test_permits_feed_the_control_loop() {
+        let semaphore = AdaptiveSemaphore::new(1, 2, Duration::from_secs(60));
+        let permit = semaphore.wait();
+        assert_eq!(semaphore.current_count(), 1);
+        drop(permit);
+        // nobody queued, the capacity stays
+        assert_eq!(semaphore.capacity(), 1);
+        assert_eq!(semaphore.current_count(), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "the capacity bounds 4..=2 are empty")]
+    fn test_empty_bounds() {
+        AdaptiveSemaphore::new(4, 2, Duration::ZERO);
+    }
+}
@@ -0,0 +1,162 @@
+use crate::naive_semaphore::{NaiveSemaphore, SemaphorePermit};
+
+/// A `NaiveSemaphore` with a single permit, gating a critical section across threads like a
+/// mutex that does not own the data it protects.
+///
+/// Unlike a `Mutex`, it can be unlocked from another thread than the one that locked it.
+///
+/// ```
+/// use naive_semaphore::BinarySemaphore;
+///
+/// let gate = BinarySemaphore::new();
+///
+/// let guard = gate.lock();
+/// assert!(gate.try_lock().is_none());
+///
+/// drop(guard);
+/// assert!(gate.try_lock().is_some());
+/// ```
+///
+/// Only the methods keeping the single permit are forwarded to the `NaiveSemaphore`, the
+/// capacity cannot be changed:
+///
+/// ```compile_fail
+/// use naive_semaphore::BinarySemaphore;
+///
+/// let gate = BinarySemaphore::new();
+/// gate.set_max(2);
+/// ```
+pub struct BinarySemaphore {
+    semaphore: NaiveSemaphore,
+}
+
+impl BinarySemaphore {
+    /// Create an unlocked binary semaphore.
+    pub fn new() -> Self {
+        Self {
+            semaphore: NaiveSemaphore::new(1),
+        }
+    }
+
+    /// Block a thread until the gate is unlocked and lock it.
+    /// The returned guard unlocks the gate again once it is dropped.
+    pub fn lock(&self) -> SemaphorePermit<'_> {
+        self.semaphore.wait()
+    }
+
+    /// Lock the gate only if it is unlocked right now, never blocks.
+    pub fn try_lock(&self) -> Option<SemaphorePermit<'_>> {
+        self.semaphore.try_wait()
+    }
+
+    /// Unlock a gate whose guard was forgotten, e.g. to unlock it from another thread.
+    /// The same as `release_one`.
+    pub fn unlock(&self) {
+        self.semaphore.release_one();
+    }
+
+    /// Whether the gate is locked right now.
+    pub fn is_locked(&self) -> bool {
+        self.semaphore.is_full()
+    }
+
+    /// The same as `lock`.
+    pub fn wait(&self) -> SemaphorePermit<'_> {
+        self.lock()
+    }
+
+    /// The same as `try_lock`.
+    pub fn try_wait(&self) -> Option<SemaphorePermit<'_>> {
+        self.try_lock()
+    }
+
+    /// Give back the permit of a forgotten guard, see `NaiveSemaphore::release_one`.
+    /// Returns whether it woke a waiting thread.
+    pub fn release_one(&self) -> bool {
+        self.semaphore.release_one()
+    }
+
+    /// One while the gate is locked, zero otherwise.
+    pub fn current_count(&self) -> usize {
+        self.semaphore.current_count()
+    }
+
+    /// One while the gate is unlocked, zero otherwise.
+    pub fn available_permits(&self) -> usize {
+        self.semaphore.available_permits()
+    }
+}
+
+impl Default for BinarySemaphore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[test]
+    fn test_mutual_exclusion() {
+        let gate = Arc::new(BinarySemaphore::new());
+        let inside = Arc::new(AtomicUsize::new(0));
+
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let gate = Arc::clone(&gate);
+                let inside = Arc::clone(&inside);
+                std::thread::spawn(move || {
+                    for _ in 0..500 {
+                        let _guard = gate.lock();
+                        assert_eq!(inside.fetch_add(1, Ordering::SeqCst), 0);
+                        std::thread::yield_now();
+                        inside.fetch_sub(1, Ordering::SeqCst);
+                    }
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert!(!gate.is_locked());
+    }
+
+    #[test]
+    fn test_unlock_from_another_thread() {
+        let gate = Arc::new(BinarySemaphore::new());
+        std::mem::forget(gate.lock());
+        assert!(gate.is_locked());
+
+        let other = Arc::clone(&gate);
+        std::thread::spawn(move || other.unlock()).join().unwrap();
+        assert!(!gate.is_locked());
+    }
+
+    #[test]
+    fn test_second_lock_blocks() {
+        let gate = Arc::new(BinarySemaphore::new());
+        let entered = Arc::new(AtomicBool::new(false));
+        let guard = gate.lock();
+        assert!(gate.try_lock().is_none());
+
+        let (other, other_entered) = (Arc::clone(&gate), Arc::clone(&entered));
+        let handle = std::thread::spawn(move || {
+            let _guard = other.lock();
+            other_entered.store(true, Ordering::SeqCst);
+        });
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(!entered.load(Ordering::SeqCst));
+        assert_eq!(gate.current_count(), 1);
+        assert_eq!(gate.available_permits(), 0);
+
+        drop(guard);
+        handle.join().unwrap();
+        assert!(entered.load(Ordering::SeqCst));
+        assert_eq!(gate.current_count(), 0);
+    }
+}
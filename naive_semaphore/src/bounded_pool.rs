@@ -0,0 +1,171 @@
+use crate::naive_semaphore::NaiveSemaphore;
+use crate::sync::Mutex;
+use std::any::Any;
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+/// The spawned threads of a `BoundedPool` not joined yet.
+#[derive(Default)]
+struct Running {
+    handles: Vec<JoinHandle<()>>,
+    // The first panic of a task joined early, propagated by the next `join`.
+    panic: Option<Box<dyn Any + Send>>,
+}
+
+impl Running {
+    /// Join the threads that already finished, so that the handles do not pile up.
+    fn join_finished(&mut self) {
+        let (finished, running) = std::mem::take(&mut self.handles)
+            .into_iter()
+            .partition(JoinHandle::is_finished);
+        self.handles = running;
+        for handle in finished {
+            self.record(handle.join());
+        }
+    }
+
+    fn record(&mut self, result: std::thread::Result<()>) {
+        if let Err(panic) = result {
+            self.panic.get_or_insert(panic);
+        }
+    }
+}
+
+/// A bounded executor running each task on a thread of its own, at most 'max' at a time.
+/// Submitting blocks while 'max' tasks are running, so a fast producer is slowed down to
+/// the pace of the tasks instead of spawning without limit.
+///
+/// ```
+/// use naive_semaphore::BoundedPool;
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+/// use std::sync::Arc;
+///
+/// let pool = BoundedPool::new(2);
+/// let done = Arc::new(AtomicUsize::new(0));
+/// for _ in 0..8 {
+///     let done = Arc::clone(&done);
+///     pool.execute(move || {
+///         done.fetch_add(1, Ordering::SeqCst);
+///     });
+/// }
+///
+/// pool.join();
+/// assert_eq!(done.load(Ordering::SeqCst), 8);
+/// ```
+pub struct BoundedPool {
+    semaphore: Arc<NaiveSemaphore>,
+    running: Mutex<Running>,
+}
+
+impl BoundedPool {
+    /// Create a pool running at most 'max' tasks at a time.
+    ///
+    /// # Panics
+    ///
+    /// Panics if 'max' is zero.
+    pub fn new(max: usize) -> Self {
+        Self {
+            semaphore: Arc::new(NaiveSemaphore::new(max)),
+            running: Mutex::new(Running::default()),
+        }
+    }
+
+    /// The count of tasks currently running.
+    pub fn running_count(&self) -> usize {
+        self.semaphore.current_count()
+    }
+
+    /// Run 'task' on a new thread, blocking until fewer than 'max' tasks are running.
+    /// The task's slot is freed as soon as it returns, also on panic.
+    pub fn execute<F>(&self, task: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let permit = self.semaphore.acquire_owned();
+        let handle = std::thread::spawn(move || {
+            let _permit = permit;
+            task();
+        });
+        let mut running = self.running.lock();
+        running.join_finished();
+        running.handles.push(handle);
+    }
+
+    /// Block until every task submitted so far finished.
+    ///
+    /// # Panics
+    ///
+    /// Propagates the panic of a task once all of them were joined.
+    pub fn join(&self) {
+        let (handles, panic) = {
+            let mut running = self.running.lock();
+            (std::mem::take(&mut running.handles), running.panic.take())
+        };
+        let mut joined = Running {
+            handles: Vec::new(),
+            panic,
+        };
+        for handle in handles {
+            joined.record(handle.join());
+        }
+        if let Some(panic) = joined.panic {
+            std::panic::resume_unwind(panic);
+        }
+    }
+}
+
+impl Drop for BoundedPool {
+    /// Wait for the outstanding tasks, their panics are not propagated here.
+    fn drop(&mut self) {
+        for handle in std::mem::take(&mut self.running.lock().handles) {
+            let _ = handle.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[test]
+    fn test_concurrency_bounded_by_max() {
+        let pool = BoundedPool::new(4);
+        let running = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let done = Arc::new(AtomicUsize::new(0));
+
+        for _ in 0..32 {
+            let running = Arc::clone(&running);
+            let peak = Arc::clone(&peak);
+            let done = Arc::clone(&done);
+            pool.execute(move || {
+                let now_running = running.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(now_running, Ordering::SeqCst);
+                std::thread::sleep(Duration::from_millis(2));
+                running.fetch_sub(1, Ordering::SeqCst);
+                done.fetch_add(1, Ordering::SeqCst);
+            });
+            assert!(pool.running_count() <= 4);
+        }
+        pool.join();
+
+        assert_eq!(done.load(Ordering::SeqCst), 32);
+        assert!(peak.load(Ordering::SeqCst) <= 4);
+        assert_eq!(pool.running_count(), 0);
+    }
+
+    #[test]
+    fn test_join_propagates_panics() {
+        let pool = BoundedPool::new(2);
+        pool.execute(|| panic!("task failed"));
+        pool.execute(|| {});
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| pool.join()));
+        assert!(result.is_err());
+        // the failed task gave its slot back
+        assert_eq!(pool.running_count(), 0);
+        pool.join();
+    }
+}
@@ -0,0 +1,79 @@
+use crate::naive_semaphore::Parking;
+use crate::sync::atomic::{AtomicBool, Ordering};
+use crate::sync::Mutex;
+use std::sync::Arc;
+
+struct CancelState {
+    cancelled: AtomicBool,
+    // The semaphores with a thread parked in `wait_cancellable` on this token,
+    // once per parked thread.
+    parked: Mutex<Vec<Arc<Parking>>>,
+}
+
+/// A flag shared between the threads of a job, tripped once to make every
+/// `NaiveSemaphore::wait_cancellable` on it give up, including the ones already blocked.
+///
+/// Clones share the same flag.
+///
+/// ```
+/// use naive_semaphore::{AcquireError, CancelToken, NaiveSemaphore};
+///
+/// let semaphore = NaiveSemaphore::new(1);
+/// let token = CancelToken::new();
+///
+/// token.cancel();
+/// assert_eq!(semaphore.wait_cancellable(&token).err(), Some(AcquireError::Cancelled));
+/// ```
+#[derive(Clone)]
+pub struct CancelToken {
+    state: Arc<CancelState>,
+}
+
+impl CancelToken {
+    /// Create a token that is not cancelled yet.
+    pub fn new() -> Self {
+        Self {
+            state: Arc::new(CancelState {
+                cancelled: AtomicBool::new(false),
+                parked: Mutex::new(Vec::new()),
+            }),
+        }
+    }
+
+    /// Trip the flag and wake up every thread blocked on this token, cannot be undone.
+    pub fn cancel(&self) {
+        self.state.cancelled.store(true, Ordering::SeqCst);
+        for parking in self.state.parked.lock().iter() {
+            parking.notify_all();
+        }
+    }
+
+    /// Whether `cancel` was called on this token or one of its clones.
+    pub fn is_cancelled(&self) -> bool {
+        self.state.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Have `cancel` wake up the threads blocked in 'parking' until `deregister`.
+    pub(crate) fn register(&self, parking: &Arc<Parking>) {
+        self.state.parked.lock().push(Arc::clone(parking));
+    }
+
+    pub(crate) fn deregister(&self, parking: &Arc<Parking>) {
+        let mut parked = self.state.parked.lock();
+        if let Some(idx) = parked.iter().position(|p| Arc::ptr_eq(p, parking)) {
+            parked.swap_remove(idx);
+        }
+    }
+
+    /// The count of threads registered to be woken up by `cancel`.
+    #[cfg(test)]
+    pub(crate) fn registrations(&self) -> usize {
+        self.state.parked.lock().len()
+    }
+}
+
+impl Default for CancelToken {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,84 @@
+use crate::error::InvalidConfig;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// The capacity and occupancy of a `NaiveSemaphore`, e.g. to persist a pool configuration and
+/// restore it later, see `NaiveSemaphore::to_config` and `NaiveSemaphore::from_config`.
+///
+/// Always valid: 'max' is at least one and 'current' never exceeds it, which is also checked
+/// when deserializing with the `serde` feature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(try_from = "ConfigFields")
+)]
+pub struct SemaphoreConfig {
+    max: usize,
+    current: usize,
+}
+
+impl SemaphoreConfig {
+    /// A config for a semaphore admitting 'max' threads, of which 'current' are running.
+    /// Fails unless `0 < max` and `current <= max`.
+    pub fn new(max: usize, current: usize) -> Result<Self, InvalidConfig> {
+        if max == 0 || current > max {
+            return Err(InvalidConfig);
+        }
+        Ok(Self { max, current })
+    }
+
+    /// The capacity.
+    pub fn max(&self) -> usize {
+        self.max
+    }
+
+    /// The count of running threads.
+    pub fn current(&self) -> usize {
+        self.current
+    }
+}
+
+/// The fields as deserialized, before they are validated by `SemaphoreConfig::new`.
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+struct ConfigFields {
+    max: usize,
+    current: usize,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<ConfigFields> for SemaphoreConfig {
+    type Error = InvalidConfig;
+
+    fn try_from(fields: ConfigFields) -> Result<Self, InvalidConfig> {
+        Self::new(fields.max, fields.current)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new_validates() {
+        assert!(SemaphoreConfig::new(2, 2).is_ok());
+        assert_eq!(SemaphoreConfig::new(0, 0), Err(InvalidConfig));
+        assert_eq!(SemaphoreConfig::new(2, 3), Err(InvalidConfig));
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_serde_round_trip() {
+        let config = SemaphoreConfig::new(4, 1).unwrap();
+        let json = serde_json::to_string(&config).unwrap();
+        assert_eq!(json, r#"{"max":4,"current":1}"#);
+        assert_eq!(
+            serde_json::from_str::<SemaphoreConfig>(&json).unwrap(),
+            config
+        );
+
+        let invalid = serde_json::from_str::<SemaphoreConfig>(r#"{"max":1,"current":2}"#);
+        assert!(invalid.is_err());
+    }
+}
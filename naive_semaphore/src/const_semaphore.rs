@@ -0,0 +1,196 @@
+use crate::naive_semaphore::Counter;
+use crate::permits::Permits;
+use crate::sync::{Condvar, Mutex};
+
+/// A counting semaphore with its capacity 'N' fixed at compile time, blocking threads on a
+/// `Mutex`/`Condvar` pair like `NaiveSemaphore` and counting permits with the same `Permits`.
+/// The capacity never changes, and a capacity of zero does not compile:
+///
+/// ```compile_fail
+/// use naive_semaphore::ConstSemaphore;
+///
+/// let semaphore = ConstSemaphore::<0>::new();
+/// ```
+///
+/// It has none of the extras of `NaiveSemaphore`, use that one to change the capacity at
+/// runtime, time out or observe the semaphore.
+///
+/// ```
+/// use naive_semaphore::ConstSemaphore;
+///
+/// let semaphore = ConstSemaphore::<2>::new();
+///
+/// let permit = semaphore.wait();
+/// assert_eq!(semaphore.available_permits(), 1);
+///
+/// drop(permit);
+/// assert_eq!(semaphore.current_count(), 0);
+/// ```
+pub struct ConstSemaphore<const N: usize> {
+    permits: Permits,
+    // The count of threads about to park or parked, so releases only lock while some wait.
+    waiters: Counter,
+    lock: Mutex<()>,
+    waiter: Condvar,
+}
+
+impl<const N: usize> ConstSemaphore<N> {
+    const NON_ZERO: () = assert!(N > 0, "a semaphore needs a capacity of at least one");
+
+    /// Create a semaphore admitting at most 'N' threads at a time.
+    pub fn new() -> Self {
+        // evaluated per 'N', failing the build for a capacity of zero
+        let () = Self::NON_ZERO;
+        Self {
+            permits: Permits::new(N),
+            waiters: Counter::new(0),
+            lock: Mutex::new(()),
+            waiter: Condvar::new(),
+        }
+    }
+
+    /// The capacity, i.e. 'N'.
+    pub const fn max_permits(&self) -> usize {
+        N
+    }
+
+    /// The count of currently running threads.
+    pub fn current_count(&self) -> usize {
+        self.permits.current()
+    }
+
+    /// The count of threads that could start right now without waiting.
+    pub fn available_permits(&self) -> usize {
+        N.saturating_sub(self.current_count())
+    }
+
+    /// Block a thread until one of the 'N' permits is free.
+    /// The returned permit releases the slot again once it is dropped.
+    pub fn wait(&self) -> ConstPermit<'_, N> {
+        if let Some(permit) = self.try_wait() {
+            return permit;
+        }
+        let mut guard = self.lock.lock();
+        // counted in before the last check for room, so a release either sees the waiter or
+        // the check sees the release, see `Counter`
+        self.waiters.add(1);
+        while !self.permits.try_acquire(1) {
+            guard = self.waiter.wait(guard);
+        }
+        // admitted under the lock, like in `NaiveSemaphore` the decrement needs no ordering
+        self.waiters.sub(1);
+        ConstPermit { semaphore: self }
+    }
+
+    /// Acquire a permit only if one is immediately available, never blocks.
+    pub fn try_wait(&self) -> Option<ConstPermit<'_, N>> {
+        self.permits
+            .try_acquire(1)
+            .then(|| ConstPermit { semaphore: self })
+    }
+
+    fn release(&self) {
+        self.permits.release(1);
+        // a read-modify-write, a load could miss a waiter counted in concurrently
+        if self.waiters.get_latest() > 0 {
+            // a waiter between its check and parking holds the lock, so it cannot miss this
+            let _guard = self.lock.lock();
+            self.waiter.notify_one();
+        }
+    }
+}
+
+impl<const N: usize> Default for ConstSemaphore<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A permit acquired from a `ConstSemaphore`, released when dropped (also on panic).
+#[must_use = "the permit is released immediately if it is not held"]
+pub struct ConstPermit<'a, const N: usize> {
+    semaphore: &'a ConstSemaphore<N>,
+}
+
+impl<const N: usize> Drop for ConstPermit<'_, N> {
+    fn drop(&mut self) {
+        self.semaphore.release();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::NaiveSemaphore;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    /// The peak count of concurrently running threads of 8 threads acquiring with 'wait'.
+    fn peak_running<G>(wait: impl Fn() -> G + Sync) -> usize {
+        let running = AtomicUsize::new(0);
+        let peak = AtomicUsize::new(0);
+        std::thread::scope(|s| {
+            for _ in 0..8 {
+                s.spawn(|| {
+                    for _ in 0..20 {
+                        let _permit = wait();
+                        let now_running = running.fetch_add(1, Ordering::SeqCst) + 1;
+                        peak.fetch_max(now_running, Ordering::SeqCst);
+                        std::thread::sleep(Duration::from_micros(100));
+                        running.fetch_sub(1, Ordering::SeqCst);
+                    }
+                });
+            }
+        });
+        peak.load(Ordering::SeqCst)
+    }
+
+    #[test]
+    fn test_behaves_like_runtime_capacity() {
+        let fixed = ConstSemaphore::<2>::new();
+        let runtime = NaiveSemaphore::new(2);
+        assert_eq!(fixed.max_permits(), runtime.max_permits());
+
+        let (first, first_runtime) = (fixed.try_wait(), runtime.try_wait());
+        let (second, second_runtime) = (fixed.try_wait(), runtime.try_wait());
+        assert!(first.is_some() && first_runtime.is_some());
+        assert!(second.is_some() && second_runtime.is_some());
+        assert!(fixed.try_wait().is_none() && runtime.try_wait().is_none());
+        assert_eq!(fixed.current_count(), runtime.current_count());
+
+        drop((first, first_runtime));
+        assert_eq!(fixed.available_permits(), runtime.available_permits());
+        drop((second, second_runtime));
+        assert_eq!(fixed.current_count(), 0);
+
+        assert!(peak_running(|| fixed.wait()) <= 2);
+        assert!(peak_running(|| runtime.wait()) <= 2);
+        assert_eq!(fixed.current_count(), runtime.current_count());
+    }
+}
+
+/// Model tests of the wakeup handshake, run together with the ones in `naive_semaphore.rs`.
+#[cfg(all(test, loom))]
+mod loom_tests {
+    use super::*;
+    use loom::sync::Arc;
+    use loom::thread;
+
+    #[test]
+    fn loom_release_wakes_waiter() {
+        let mut builder = loom::model::Builder::new();
+        builder.preemption_bound = Some(3);
+        builder.check(|| {
+            let semaphore = Arc::new(ConstSemaphore::<1>::new());
+            let permit = semaphore.wait();
+
+            // a release missing the waiter leaves it parked for good, which loom reports
+            let other = Arc::clone(&semaphore);
+            let handle = thread::spawn(move || drop(other.wait()));
+            drop(permit);
+            handle.join().unwrap();
+
+            assert_eq!(semaphore.current_count(), 0);
+        });
+    }
+}
@@ -0,0 +1,110 @@
+use core::fmt;
+
+/// Returned by `acquire_timeout` when no permit became available in time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timeout;
+
+impl fmt::Display for Timeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "timed out waiting for a semaphore permit")
+    }
+}
+
+impl core::error::Error for Timeout {}
+
+/// Returned by `try_release_n` when releasing more permits than are currently taken,
+/// e.g. on a double release.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OverRelease;
+
+impl fmt::Display for OverRelease {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "released more semaphore permits than were acquired")
+    }
+}
+
+impl core::error::Error for OverRelease {}
+
+/// Returned by `SemaphoreConfig::new` for a capacity of zero or more running threads than
+/// the capacity, also when deserializing such a config. The builders return it for a
+/// capacity of zero and for options that do not go together.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidConfig;
+
+impl fmt::Display for InvalidConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid semaphore configuration")
+    }
+}
+
+impl core::error::Error for InvalidConfig {}
+
+/// Returned by `acquire_many_owned` when requesting more permits than the capacity,
+/// since such a request could never be satisfied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExceedsCapacity;
+
+impl fmt::Display for ExceedsCapacity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "requested more permits than the semaphore's capacity")
+    }
+}
+
+impl core::error::Error for ExceedsCapacity {}
+
+/// Returned by `NaiveSemaphore::try_new` for a capacity of zero.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidCapacity;
+
+impl fmt::Display for InvalidCapacity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "a semaphore needs a capacity of at least one")
+    }
+}
+
+impl core::error::Error for InvalidCapacity {}
+
+/// Any of the ways an acquisition can fail, returned by every fallible acquisition of
+/// `NaiveSemaphore`, so callers match on a single type. `Timeout` converts into it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum AcquireError {
+    /// No permit was free and the acquisition does not block, see `try_acquire`.
+    WouldBlock,
+    /// No permit became free in time, see `wait_timeout`.
+    TimedOut,
+    /// The semaphore was closed for shutdown, see `close`.
+    Closed,
+    /// The `CancelToken` was cancelled before a permit was free, see `wait_cancellable`.
+    Cancelled,
+    /// As many threads as allowed were waiting already, see `wait_or_overloaded`.
+    Overloaded,
+    /// The calling thread already holds a permit of the full semaphore, so that waiting for
+    /// one of its own to be released would never end, see `wait_or_would_deadlock`.
+    WouldDeadlock,
+}
+
+impl fmt::Display for AcquireError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::WouldBlock => write!(f, "no semaphore permit is free right now"),
+            Self::TimedOut => Timeout.fmt(f),
+            Self::Closed => write!(f, "the semaphore was closed"),
+            Self::Cancelled => write!(f, "cancelled waiting for a semaphore permit"),
+            Self::Overloaded => {
+                write!(f, "too many threads are waiting for a semaphore permit")
+            }
+            Self::WouldDeadlock => {
+                write!(f, "the thread already holds a permit of the full semaphore")
+            }
+        }
+    }
+}
+
+impl core::error::Error for AcquireError {}
+
+impl From<Timeout> for AcquireError {
+    fn from(_: Timeout) -> Self {
+        Self::TimedOut
+    }
+}
@@ -0,0 +1,46 @@
+use std::time::Instant;
+
+/// A lifecycle event of a `NaiveSemaphore`, streamed to the channel passed to
+/// `NaiveSemaphore::with_event_sink`. Each carries the time it happened and the current
+/// count right after it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SemaphoreEvent {
+    /// A thread found no free permit and starts blocking.
+    WaitStarted { at: Instant, current_count: usize },
+    /// 'permits' permits were acquired.
+    Acquired {
+        at: Instant,
+        current_count: usize,
+        permits: usize,
+    },
+    /// 'permits' permits were released.
+    Released {
+        at: Instant,
+        current_count: usize,
+        permits: usize,
+    },
+    /// A thread gave up waiting for a permit.
+    TimedOut { at: Instant, current_count: usize },
+}
+
+impl SemaphoreEvent {
+    /// When the event happened.
+    pub fn at(&self) -> Instant {
+        match *self {
+            Self::WaitStarted { at, .. }
+            | Self::Acquired { at, .. }
+            | Self::Released { at, .. }
+            | Self::TimedOut { at, .. } => at,
+        }
+    }
+
+    /// The count of running threads right after the event.
+    pub fn current_count(&self) -> usize {
+        match *self {
+            Self::WaitStarted { current_count, .. }
+            | Self::Acquired { current_count, .. }
+            | Self::Released { current_count, .. }
+            | Self::TimedOut { current_count, .. } => current_count,
+        }
+    }
+}
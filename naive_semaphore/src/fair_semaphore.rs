@@ -0,0 +1,371 @@
+use crate::error::{InvalidCapacity, InvalidConfig};
+use crate::sync::{Condvar, Mutex, MutexGuard};
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+struct Tickets {
+    // The count of currently running threads.
+    current: usize,
+    // The ticket handed to the next thread calling `wait`.
+    next: u64,
+    // The ticket of the thread admitted next, all earlier tickets already got in.
+    serving: u64,
+    // The private condvars of the parked threads in ticket order, see `with_handoff`.
+    queue: VecDeque<Arc<Condvar>>,
+}
+
+/// A counting semaphore admitting threads strictly in the order they called `wait`.
+///
+/// Every waiter draws a ticket and only the oldest ticket may take a free slot, so no thread
+/// can starve behind later arrivals. The price is that each admission and release wakes all
+/// waiters to let them compare their ticket, unless the semaphore was created with
+/// `with_handoff`.
+///
+/// ```
+/// use naive_semaphore::FairSemaphore;
+///
+/// let semaphore = FairSemaphore::new(1);
+///
+/// let permit = semaphore.wait();
+/// assert!(semaphore.try_wait().is_none());
+///
+/// drop(permit);
+/// assert_eq!(semaphore.current_count(), 0);
+/// ```
+pub struct FairSemaphore {
+    max: usize,
+    tickets: Mutex<Tickets>,
+    waiter: Condvar,
+    // Whether releases hand their slot to the oldest waiter directly, see `with_handoff`.
+    handoff: bool,
+}
+
+impl FairSemaphore {
+    /// Create a fair semaphore admitting at most 'max' threads at a time.
+    ///
+    /// # Panics
+    ///
+    /// Panics if 'max' is zero, see `try_new`.
+    pub fn new(max: usize) -> Self {
+        Self::try_new(max).expect("a semaphore needs a capacity of at least one")
+    }
+
+    /// Create a fair semaphore admitting at most 'max' threads at a time.
+    /// Fails for a 'max' of zero, since such a semaphore could never admit a thread.
+    pub fn try_new(max: usize) -> Result<Self, InvalidCapacity> {
+        if max == 0 {
+            return Err(InvalidCapacity);
+        }
+        Ok(Self {
+            max,
+            tickets: Mutex::new(Tickets {
+                current: 0,
+                next: 0,
+                serving: 0,
+                queue: VecDeque::new(),
+            }),
+            waiter: Condvar::new(),
+            handoff: false,
+        })
+    }
+
+    /// Like `new`, but every waiter parks on a condvar of its own and a release hands its
+    /// slot over to the oldest waiter, waking up exactly that thread instead of all of them.
+    ///
+    /// # Panics
+    ///
+    /// Panics if 'max' is zero, see `try_new`.
+    pub fn with_handoff(max: usize) -> Self {
+        let mut semaphore = Self::new(max);
+        semaphore.handoff = true;
+        semaphore
+    }
+
+    /// The count of currently running threads.
+    pub fn current_count(&self) -> usize {
+        self.lock().current
+    }
+
+    /// The count of threads that could start right now without blocking.
+    pub fn available_permits(&self) -> usize {
+        let tickets = self.lock();
+        if tickets.next == tickets.serving {
+            self.max.saturating_sub(tickets.current)
+        } else {
+            // queued threads claim any free slot first
+            0
+        }
+    }
+
+    /// Block a thread until all earlier callers were admitted and a slot is free.
+    /// The returned permit releases the slot again once it is dropped.
+    pub fn wait(&self) -> FairSemaphorePermit<'_> {
+        let mut tickets = self.lock();
+        let ticket = tickets.next;
+        tickets.next += 1;
+
+        if self.handoff {
+            return self.wait_for_handoff(tickets, ticket);
+        }
+        while tickets.serving != ticket || tickets.current >= self.max {
+            tickets = self.waiter.wait(tickets);
+        }
+        tickets.serving += 1;
+        tickets.current += 1;
+        // the next ticket may fit as well
+        self.waiter.notify_all();
+
+        FairSemaphorePermit { semaphore: self }
+    }
+
+    /// The `wait` of `with_handoff`: get in right away if nobody is queued and a slot is
+    /// free, otherwise queue up until a release passes its slot on.
+    fn wait_for_handoff<'a>(
+        &'a self,
+        mut tickets: MutexGuard<'a, Tickets>,
+        ticket: u64,
+    ) -> FairSemaphorePermit<'a> {
+        if tickets.serving == ticket && tickets.current < self.max {
+            tickets.serving += 1;
+            tickets.current += 1;
+        } else {
+            let waiter = Arc::new(Condvar::new());
+            tickets.queue.push_back(Arc::clone(&waiter));
+            // admitted once the handoff reached this ticket, spurious wakeups re-check
+            while tickets.serving <= ticket {
+                tickets = waiter.wait(tickets);
+            }
+        }
+        FairSemaphorePermit { semaphore: self }
+    }
+
+    /// Acquire a permit only if one is free and nobody is queued, never blocks.
+    pub fn try_wait(&self) -> Option<FairSemaphorePermit<'_>> {
+        let mut tickets = self.lock();
+        if tickets.next != tickets.serving || tickets.current >= self.max {
+            return None;
+        }
+        tickets.next += 1;
+        tickets.serving += 1;
+        tickets.current += 1;
+
+        Some(FairSemaphorePermit { semaphore: self })
+    }
+
+    /// Release a slot and let the oldest waiting thread in.
+    ///
+    /// Without `with_handoff` nobody queues and `current` drops with every release. With it,
+    /// `current` also counts the slots handed over to queued threads that did not wake up yet,
+    /// so `try_wait` cannot take them in the meantime.
+    pub fn release_one(&self) {
+        let mut tickets = self.lock();
+        if let Some(waiter) = tickets.queue.pop_front() {
+            // the slot stays taken and passes to the oldest ticket
+            tickets.serving += 1;
+            // every ticket drawn but not admitted yet is still queued, and threads only
+            // queue up while all slots are taken, which no handoff changes
+            debug_assert_eq!(
+                tickets.queue.len() as u64,
+                tickets.next - tickets.serving,
+                "a handoff skipped or repeated a ticket"
+            );
+            debug_assert_eq!(
+                tickets.current, self.max,
+                "a slot was freed while threads were queued"
+            );
+            waiter.notify_one();
+        } else if tickets.current >= 1 {
+            tickets.current -= 1;
+            self.waiter.notify_all();
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Tickets> {
+        self.tickets.lock()
+    }
+}
+
+/// Configures a `FairSemaphore`, created with `SemaphoreBuilder::fair`.
+///
+/// ```
+/// use naive_semaphore::NaiveSemaphore;
+///
+/// let semaphore = NaiveSemaphore::builder().max(2).fair().handoff().build().unwrap();
+/// drop(semaphore.wait());
+///
+/// // a fair semaphore has none of the options of a `NaiveSemaphore`
+/// assert!(NaiveSemaphore::builder().max(2).stats().fair().build().is_err());
+/// ```
+#[must_use = "a builder does nothing unless `build` is called"]
+pub struct FairSemaphoreBuilder {
+    max: usize,
+    handoff: bool,
+    // Whether options only a `NaiveSemaphore` supports were set before switching over.
+    unsupported: bool,
+}
+
+impl FairSemaphoreBuilder {
+    pub(crate) fn new(max: usize, unsupported: bool) -> Self {
+        Self {
+            max,
+            handoff: false,
+            unsupported,
+        }
+    }
+
+    /// Hand released slots over to the oldest waiter, see `FairSemaphore::with_handoff`.
+    pub fn handoff(mut self) -> Self {
+        self.handoff = true;
+        self
+    }
+
+    /// Create the configured semaphore. Fails if 'max' is zero or was never set, or if any
+    /// option other than 'max' was set on the `SemaphoreBuilder` before `fair`.
+    pub fn build(self) -> Result<FairSemaphore, InvalidConfig> {
+        if self.unsupported {
+            return Err(InvalidConfig);
+        }
+        let mut semaphore = FairSemaphore::try_new(self.max).map_err(|_| InvalidConfig)?;
+        semaphore.handoff = self.handoff;
+        Ok(semaphore)
+    }
+}
+
+/// A permit acquired from a `FairSemaphore`, released when dropped (also on panic).
+#[must_use = "the permit is released immediately if it is not held"]
+pub struct FairSemaphorePermit<'a> {
+    semaphore: &'a FairSemaphore,
+}
+
+impl Drop for FairSemaphorePermit<'_> {
+    fn drop(&mut self) {
+        self.semaphore.release_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    #[test]
+    fn test_try_wait_respects_queue() {
+        let semaphore = Arc::new(FairSemaphore::new(1));
+        let held = semaphore.wait();
+        let (release_tx, release_rx) = std::sync::mpsc::channel::<()>();
+
+        let sem = Arc::clone(&semaphore);
+        let queued = std::thread::spawn(move || {
+            let _permit = sem.wait();
+            release_rx.recv().unwrap();
+        });
+        std::thread::sleep(Duration::from_millis(20));
+
+        drop(held);
+        // the freed slot belongs to the queued thread, not to a newcomer
+        assert!(semaphore.try_wait().is_none());
+
+        release_tx.send(()).unwrap();
+        queued.join().unwrap();
+        assert!(semaphore.try_wait().is_some());
+    }
+
+    #[test]
+    fn test_try_wait_respects_handoff() {
+        let semaphore = Arc::new(FairSemaphore::with_handoff(1));
+        let held = semaphore.wait();
+        let (release_tx, release_rx) = std::sync::mpsc::channel::<()>();
+
+        let sem = Arc::clone(&semaphore);
+        let queued = std::thread::spawn(move || {
+            let _permit = sem.wait();
+            release_rx.recv().unwrap();
+        });
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        while semaphore.lock().queue.is_empty() {
+            assert!(
+                std::time::Instant::now() < deadline,
+                "the thread never queued"
+            );
+            std::thread::sleep(Duration::from_millis(1));
+        }
+
+        drop(held);
+        // the slot was handed to the queued thread, whether it woke up already or not
+        assert_eq!(semaphore.current_count(), 1);
+        assert!(semaphore.try_wait().is_none());
+
+        release_tx.send(()).unwrap();
+        queued.join().unwrap();
+        assert!(semaphore.try_wait().is_some());
+    }
+
+    #[test]
+    fn test_admission_in_arrival_order() {
+        let semaphore = Arc::new(FairSemaphore::new(1));
+        let admitted = Arc::new(Mutex::new(Vec::new()));
+        let held = semaphore.wait();
+
+        let handles: Vec<_> = (0..8)
+            .map(|idx| {
+                let semaphore = Arc::clone(&semaphore);
+                let admitted = Arc::clone(&admitted);
+                let handle = std::thread::spawn(move || {
+                    let _permit = semaphore.wait();
+                    admitted.lock().unwrap().push(idx);
+                });
+                // give each thread time to draw its ticket before the next one arrives
+                std::thread::sleep(Duration::from_millis(10));
+                handle
+            })
+            .collect();
+
+        drop(held);
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(*admitted.lock().unwrap(), (0..8).collect::<Vec<_>>());
+        assert_eq!(semaphore.current_count(), 0);
+    }
+
+    #[test]
+    fn test_handoff_in_enqueue_order() {
+        // a single slot, so that the admitted threads record themselves one after the other
+        let semaphore = Arc::new(FairSemaphore::with_handoff(1));
+        let admitted = Arc::new(Mutex::new(Vec::new()));
+        let held = semaphore.wait();
+
+        let handles: Vec<_> = (0..32)
+            .map(|idx| {
+                let sem = Arc::clone(&semaphore);
+                let admitted = Arc::clone(&admitted);
+                let handle = std::thread::spawn(move || {
+                    let _permit = sem.wait();
+                    admitted.lock().unwrap().push(idx);
+                    std::thread::yield_now();
+                });
+                // wait until the thread queued up before the next one arrives
+                let deadline = std::time::Instant::now() + Duration::from_secs(5);
+                while semaphore.lock().queue.len() <= idx {
+                    assert!(
+                        std::time::Instant::now() < deadline,
+                        "thread {} never queued",
+                        idx
+                    );
+                    std::thread::sleep(Duration::from_millis(1));
+                }
+                handle
+            })
+            .collect();
+
+        drop(held);
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(*admitted.lock().unwrap(), (0..32).collect::<Vec<_>>());
+        assert_eq!(semaphore.current_count(), 0);
+        // nobody is left queued, a newcomer gets in right away
+        assert!(semaphore.try_wait().is_some());
+    }
+}
@@ -0,0 +1,124 @@
+use crate::naive_semaphore::NaiveSemaphore;
+use std::ops::Deref;
+use std::sync::{Arc, Weak};
+
+/// A cloneable, shared `NaiveSemaphore`, saving the `Arc::clone` before moving it into a
+/// thread. All clones refer to the same semaphore, whose methods are reachable through deref.
+///
+/// ```
+/// use naive_semaphore::SemaphoreHandle;
+///
+/// let semaphore = SemaphoreHandle::new(2);
+/// let worker = semaphore.clone();
+///
+/// std::thread::spawn(move || {
+///     let _permit = worker.wait();
+/// })
+/// .join()
+/// .unwrap();
+/// assert_eq!(semaphore.current_count(), 0);
+/// ```
+#[derive(Clone)]
+pub struct SemaphoreHandle {
+    semaphore: Arc<NaiveSemaphore>,
+}
+
+impl SemaphoreHandle {
+    /// Create a handle to a new semaphore admitting at most 'max' threads at a time.
+    ///
+    /// # Panics
+    ///
+    /// Panics if 'max' is zero, see `NaiveSemaphore::try_new`.
+    pub fn new(max: usize) -> Self {
+        NaiveSemaphore::new(max).into_handle()
+    }
+
+    /// A `WeakSemaphoreHandle` to the same semaphore, not keeping it alive.
+    pub fn downgrade(&self) -> WeakSemaphoreHandle {
+        WeakSemaphoreHandle {
+            semaphore: Arc::downgrade(&self.semaphore),
+        }
+    }
+}
+
+/// A handle to a semaphore owned by `SemaphoreHandle`s, like `Weak` to `Arc`: the semaphore
+/// is dropped with the last `SemaphoreHandle`, e.g. when it is evicted from a cache.
+#[derive(Clone)]
+pub struct WeakSemaphoreHandle {
+    semaphore: Weak<NaiveSemaphore>,
+}
+
+impl WeakSemaphoreHandle {
+    /// A `SemaphoreHandle` to the semaphore, or `None` if it was dropped already.
+    pub fn upgrade(&self) -> Option<SemaphoreHandle> {
+        self.semaphore
+            .upgrade()
+            .map(|semaphore| SemaphoreHandle { semaphore })
+    }
+}
+
+impl From<NaiveSemaphore> for SemaphoreHandle {
+    fn from(semaphore: NaiveSemaphore) -> Self {
+        Self {
+            semaphore: Arc::new(semaphore),
+        }
+    }
+}
+
+impl Deref for SemaphoreHandle {
+    type Target = NaiveSemaphore;
+
+    fn deref(&self) -> &NaiveSemaphore {
+        &self.semaphore
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[test]
+    fn test_weak_handle() {
+        let semaphore = SemaphoreHandle::new(1);
+        let clone = semaphore.clone();
+        let weak = semaphore.downgrade();
+
+        let upgraded = weak.upgrade().unwrap();
+        let permit = upgraded.wait();
+        assert_eq!(semaphore.current_count(), 1);
+        drop(permit);
+        drop(upgraded);
+
+        drop(semaphore);
+        assert!(weak.upgrade().is_some());
+        drop(clone);
+        assert!(weak.upgrade().is_none());
+    }
+
+    #[test]
+    fn test_clones_share_the_semaphore() {
+        let max = 2;
+        let semaphore = SemaphoreHandle::new(max);
+        let running = Arc::new(AtomicUsize::new(0));
+
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let semaphore = semaphore.clone();
+                let running = Arc::clone(&running);
+                std::thread::spawn(move || {
+                    let _permit = semaphore.wait();
+                    assert!(running.fetch_add(1, Ordering::SeqCst) < max);
+                    std::thread::sleep(Duration::from_millis(2));
+                    running.fetch_sub(1, Ordering::SeqCst);
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(semaphore.current_count(), 0);
+    }
+}
@@ -1 +1,145 @@
-mod naive_semaphore;
+//! Semaphores built from simple logic and primitive types.
+//!
+//! `NaiveSemaphore` is the main type, blocking threads on a `Mutex`/`Condvar` pair
+//! from `std::sync`, or from `parking_lot` with the feature of the same name.
+//! Everything but the `permits` and `raw` modules needs the default `std` feature,
+//! see `raw` for the spin-based semaphore left with `default-features = false`.
+#![cfg_attr(not(feature = "std"), no_std)]
+// Interior mutability only through atomics and locks, see the assertions at the bottom.
+#![deny(unsafe_code)]
+
+#[cfg(all(test, not(feature = "std")))]
+extern crate std;
+
+#[cfg(feature = "async")]
+pub mod acquire;
+#[cfg(feature = "std")]
+pub mod adaptive_semaphore;
+#[cfg(feature = "std")]
+pub mod binary_semaphore;
+#[cfg(feature = "std")]
+pub mod bounded_pool;
+#[cfg(feature = "std")]
+pub mod cancel;
+#[cfg(feature = "std")]
+pub mod config;
+#[cfg(feature = "std")]
+pub mod const_semaphore;
+pub mod error;
+#[cfg(feature = "std")]
+pub mod events;
+#[cfg(feature = "std")]
+pub mod fair_semaphore;
+#[cfg(feature = "std")]
+pub mod handle;
+#[cfg(feature = "std")]
+pub mod multi;
+#[cfg(feature = "std")]
+pub mod naive_semaphore;
+#[cfg(feature = "std")]
+pub mod observer;
+#[cfg(feature = "std")]
+pub mod parallel;
+pub mod permits;
+#[cfg(feature = "std")]
+pub mod rate_limiter;
+pub mod raw;
+#[cfg(feature = "std")]
+pub mod resource_pool;
+#[cfg(feature = "std")]
+pub mod rw_semaphore;
+#[cfg(feature = "std")]
+pub mod stats;
+#[cfg(feature = "std")]
+mod sync;
+#[cfg(feature = "async")]
+mod timer;
+
+#[cfg(feature = "async")]
+pub use crate::acquire::{Acquire, AcquireState, AcquireTimeout};
+#[cfg(feature = "std")]
+pub use crate::adaptive_semaphore::{AdaptivePermit, AdaptiveSemaphore};
+#[cfg(feature = "std")]
+pub use crate::binary_semaphore::BinarySemaphore;
+#[cfg(feature = "std")]
+pub use crate::bounded_pool::BoundedPool;
+#[cfg(feature = "std")]
+pub use crate::cancel::CancelToken;
+#[cfg(feature = "std")]
+pub use crate::config::SemaphoreConfig;
+#[cfg(feature = "std")]
+pub use crate::const_semaphore::{ConstPermit, ConstSemaphore};
+pub use crate::error::{
+    AcquireError, ExceedsCapacity, InvalidCapacity, InvalidConfig, OverRelease, Timeout,
+};
+#[cfg(feature = "std")]
+pub use crate::events::SemaphoreEvent;
+#[cfg(feature = "std")]
+pub use crate::fair_semaphore::{FairSemaphore, FairSemaphoreBuilder, FairSemaphorePermit};
+#[cfg(feature = "std")]
+pub use crate::handle::{SemaphoreHandle, WeakSemaphoreHandle};
+#[cfg(feature = "std")]
+pub use crate::multi::acquire_all;
+#[cfg(feature = "std")]
+pub use crate::naive_semaphore::{
+    IndexedPermit, LabeledPermit, NaiveSemaphore, OwnedSemaphorePermit, PermitIter, QueuedWait,
+    SemaphoreBuilder, SemaphorePermit, SemaphoreScope,
+};
+#[cfg(feature = "std")]
+pub use crate::observer::SemaphoreObserver;
+#[cfg(feature = "std")]
+pub use crate::parallel::parallel_map;
+#[cfg(feature = "std")]
+pub use crate::rate_limiter::RateLimiter;
+pub use crate::raw::{RawSemaphore, SpinSemaphore};
+#[cfg(feature = "std")]
+pub use crate::resource_pool::{PooledItem, ResourcePool};
+#[cfg(feature = "std")]
+pub use crate::rw_semaphore::{ExclusivePermit, RwSemaphore, SharedPermit};
+#[cfg(feature = "std")]
+pub use crate::stats::SemaphoreStats;
+
+// Sharing a semaphore by reference across threads and moving permits between them is the
+// whole point, so every field breaking `Send`/`Sync` for these types fails the build here.
+const _: fn() = || {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<RawSemaphore>();
+    assert_send_sync::<SpinSemaphore>();
+    assert_send_sync::<crate::raw::RawPermit<'static, crate::raw::SpinParker>>();
+    assert_send_sync::<crate::permits::Permits>();
+};
+
+#[cfg(feature = "std")]
+const _: fn() = || {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<NaiveSemaphore>();
+    assert_send_sync::<SemaphorePermit<'static>>();
+    assert_send_sync::<OwnedSemaphorePermit>();
+    assert_send_sync::<IndexedPermit<'static>>();
+    assert_send_sync::<LabeledPermit<'static>>();
+    assert_send_sync::<QueuedWait<'static>>();
+    assert_send_sync::<SemaphoreBuilder>();
+    assert_send_sync::<FairSemaphoreBuilder>();
+    assert_send_sync::<SemaphoreHandle>();
+    assert_send_sync::<WeakSemaphoreHandle>();
+    assert_send_sync::<CancelToken>();
+    assert_send_sync::<FairSemaphore>();
+    assert_send_sync::<FairSemaphorePermit<'static>>();
+    assert_send_sync::<BinarySemaphore>();
+    assert_send_sync::<RwSemaphore>();
+    assert_send_sync::<SharedPermit<'static>>();
+    assert_send_sync::<ExclusivePermit<'static>>();
+    assert_send_sync::<ConstSemaphore<1>>();
+    assert_send_sync::<ConstPermit<'static, 1>>();
+    assert_send_sync::<AdaptiveSemaphore>();
+    assert_send_sync::<AdaptivePermit<'static>>();
+    assert_send_sync::<RateLimiter>();
+    assert_send_sync::<BoundedPool>();
+};
+
+#[cfg(feature = "async")]
+const _: fn() = || {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Acquire<'static>>();
+    assert_send_sync::<AcquireTimeout<'static>>();
+};
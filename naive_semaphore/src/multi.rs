@@ -0,0 +1,66 @@
+use crate::naive_semaphore::{NaiveSemaphore, SemaphorePermit};
+
+/// Take one permit from each of 'semaphores' at once or none at all, never blocks. If any of
+/// them has no free permit, the ones already taken are given back right away.
+///
+/// Threads needing several semaphores can retry this instead of waiting on them one by one,
+/// which deadlocks once two threads wait for each other's semaphores in opposite order.
+///
+/// ```
+/// use naive_semaphore::{acquire_all, NaiveSemaphore};
+///
+/// let (disk, network) = (NaiveSemaphore::new(1), NaiveSemaphore::new(1));
+/// let permits = acquire_all(&[&disk, &network]).unwrap();
+/// assert!(acquire_all(&[&network]).is_none());
+///
+/// drop(permits);
+/// assert_eq!(disk.current_count() + network.current_count(), 0);
+/// ```
+pub fn acquire_all<'a>(semaphores: &[&'a NaiveSemaphore]) -> Option<Vec<SemaphorePermit<'a>>> {
+    // stops at the first semaphore without a free permit, dropping the permits collected
+    semaphores
+        .iter()
+        .map(|semaphore| semaphore.try_wait())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_acquire_all_or_none() {
+        let (a, b) = (NaiveSemaphore::new(1), NaiveSemaphore::new(1));
+        let held = b.wait();
+        assert!(acquire_all(&[&a, &b]).is_none());
+        assert_eq!(a.current_count(), 0);
+
+        drop(held);
+        let permits = acquire_all(&[&a, &b]).unwrap();
+        assert_eq!(permits.len(), 2);
+        assert_eq!(a.current_count() + b.current_count(), 2);
+    }
+
+    #[test]
+    fn test_cross_acquisition_does_not_deadlock() {
+        let (a, b) = (NaiveSemaphore::strict(1), NaiveSemaphore::strict(1));
+        std::thread::scope(|s| {
+            for order in [[&a, &b], [&b, &a]] {
+                s.spawn(move || {
+                    for _ in 0..200 {
+                        let permits = loop {
+                            match acquire_all(&order) {
+                                Some(permits) => break permits,
+                                None => std::thread::yield_now(),
+                            }
+                        };
+                        std::thread::sleep(Duration::from_micros(50));
+                        drop(permits);
+                    }
+                });
+            }
+        });
+        assert_eq!(a.current_count() + b.current_count(), 0);
+    }
+}
@@ -1,10 +1,33 @@
-#![allow(dead_code)]
-use std::sync::{
-    atomic::{AtomicUsize, Ordering},
-    Condvar, Mutex,
-};
+#[cfg(feature = "async")]
+use crate::acquire::{Acquire, AcquireState, AcquireTimeout, WakerList};
+use crate::cancel::CancelToken;
+use crate::config::SemaphoreConfig;
+use crate::error::{AcquireError, ExceedsCapacity, InvalidCapacity, InvalidConfig, OverRelease};
+use crate::events::SemaphoreEvent;
+use crate::fair_semaphore::FairSemaphoreBuilder;
+use crate::handle::SemaphoreHandle;
+use crate::observer::SemaphoreObserver;
+use crate::permits::Permits;
+use crate::stats::{SemaphoreStats, StatsRecorder, WaitHistogram};
+use crate::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use crate::sync::{Condvar, Mutex, MutexGuard};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::mpsc::SyncSender;
+use std::sync::Arc;
+#[cfg(feature = "async")]
+use std::task::{Context, Poll};
+use std::thread::{ScopedJoinHandle, ThreadId};
+use std::time::{Duration, Instant};
 
-struct Counter {
+/// The count of waiters, read by releases to skip the lock while nobody waits.
+///
+/// Waiters `add` themselves before their last check for room, releases read the count with
+/// `get_latest` after giving back permits. Both are read-modify-writes, so one of them reads
+/// from the other: either the release sees the waiter, or the waiter's `AcqRel` add acquires
+/// the release's and with it the permits given back before. `sub` and `get` need no ordering,
+/// the former only follows an admission under the lock, the latter is a mere snapshot.
+pub(crate) struct Counter {
     current: AtomicUsize,
 }
 
@@ -16,213 +39,4877 @@ impl Counter {
     }
 
     pub fn get(&self) -> usize {
-        self.current.load(Ordering::SeqCst)
+        self.current.load(Ordering::Relaxed)
     }
 
-    pub fn incr(&self) {
-        self.current.fetch_add(1, Ordering::SeqCst);
+    /// Like `get`, but as a read-modify-write: it reads the latest value in any case and
+    /// synchronizes with the `add` it reads from, see `released`.
+    pub fn get_latest(&self) -> usize {
+        self.current.fetch_add(0, Ordering::AcqRel)
     }
 
-    pub fn decr(&self) {
-        self.current.fetch_sub(1, Ordering::SeqCst);
+    pub fn add(&self, n: usize) {
+        self.current.fetch_add(n, Ordering::AcqRel);
     }
+
+    pub fn sub(&self, n: usize) {
+        self.current.fetch_sub(n, Ordering::Relaxed);
+    }
+}
+
+/// Reports a semaphore stuck at capacity, see `NaiveSemaphore::with_deadlock_timeout`.
+struct DeadlockWatchdog {
+    timeout: Duration,
+    on_deadlock: Option<Box<dyn Fn() + Send + Sync>>,
+    // Bumped whenever waiters are woken up, a stall lasts as long as this does not change.
+    wakeups: Counter,
+    // One past the `wakeups` generation reported last, so that each stall is reported once.
+    reported: AtomicUsize,
+}
+
+/// Since when a waiter has seen no progress, at which `DeadlockWatchdog::wakeups` generation.
+struct Stall {
+    generation: usize,
+    since: Instant,
+}
+
+impl DeadlockWatchdog {
+    fn new(timeout: Duration, on_deadlock: Option<Box<dyn Fn() + Send + Sync>>) -> Self {
+        Self {
+            timeout,
+            on_deadlock,
+            wakeups: Counter::new(0),
+            reported: AtomicUsize::new(0),
+        }
+    }
+
+    fn stall(&self) -> Stall {
+        Stall {
+            generation: self.wakeups.get(),
+            since: Instant::now(),
+        }
+    }
+
+    /// Whether 'stall' lasted for 'timeout' and no other waiter reported it yet.
+    /// Any progress starts a new stall instead.
+    fn is_deadlock(&self, stall: &mut Stall) -> bool {
+        let generation = self.wakeups.get();
+        if generation != stall.generation {
+            *stall = self.stall();
+            return false;
+        }
+        stall.since.elapsed() >= self.timeout
+            && self.reported.fetch_max(generation + 1, Ordering::Relaxed) <= generation
+    }
+}
+
+/// What `NaiveSemaphore::observe` reports to the observer and the event sink.
+#[derive(Clone, Copy)]
+enum Observed {
+    WaitStarted,
+    Acquired(usize),
+    Released(usize),
+    TimedOut,
+}
+
+/// Where the threads of a `NaiveSemaphore` block.
+pub(crate) struct Parking {
+    // Held while checking for room right before parking on `waiter`, and while notifying it.
+    lock: Mutex<Parked>,
+    waiter: Condvar,
+    // The count of threads returning from `waiter`, to check for needless wakeups.
+    #[cfg(test)]
+    wakeups: AtomicUsize,
+}
+
+/// The threads blocked on `Parking::waiter`, see `wake_waiters`.
+#[derive(Default)]
+struct Parked {
+    threads: usize,
+    // The part of 'threads' waiting for more than one permit.
+    bulk: usize,
+    // The threads in `wait_with_priority`, in arrival order.
+    by_priority: Vec<PriorityWaiter>,
+    next_ticket: u64,
+}
+
+struct PriorityWaiter {
+    ticket: u64,
+    priority: u8,
+    // The count of admissions this waiter was passed over for, added to its priority.
+    age: u32,
+}
+
+impl Parked {
+    /// Queue up a thread waiting with 'priority', returning its ticket.
+    fn enqueue(&mut self, priority: u8) -> u64 {
+        let ticket = self.next_ticket;
+        self.next_ticket += 1;
+        self.by_priority.push(PriorityWaiter {
+            ticket,
+            priority,
+            age: 0,
+        });
+        ticket
+    }
+
+    /// The ticket to admit next: the highest aged priority, the longest waiting among equals.
+    fn head(&self) -> Option<u64> {
+        self.by_priority
+            .iter()
+            .max_by_key(|waiter| {
+                let aged = u32::from(waiter.priority).saturating_add(waiter.age);
+                (aged, std::cmp::Reverse(waiter.ticket))
+            })
+            .map(|waiter| waiter.ticket)
+    }
+
+    /// How many waiters are admitted before 'ticket', zero for the head.
+    fn position(&self, ticket: u64) -> usize {
+        let by_rank = |waiter: &PriorityWaiter| {
+            let aged = u32::from(waiter.priority).saturating_add(waiter.age);
+            (aged, std::cmp::Reverse(waiter.ticket))
+        };
+        let Some(rank) = self
+            .by_priority
+            .iter()
+            .find(|waiter| waiter.ticket == ticket)
+        else {
+            return 0;
+        };
+        let rank = by_rank(rank);
+        self.by_priority
+            .iter()
+            .filter(|waiter| by_rank(waiter) > rank)
+            .count()
+    }
+
+    /// Remove 'ticket' leaving the queue without being admitted, nobody is passed over.
+    fn leave(&mut self, ticket: u64) {
+        self.by_priority.retain(|waiter| waiter.ticket != ticket);
+    }
+
+    /// Remove the admitted 'ticket' and age everyone passed over.
+    fn dequeue(&mut self, ticket: u64) {
+        self.by_priority.retain(|waiter| waiter.ticket != ticket);
+        for waiter in &mut self.by_priority {
+            waiter.age = waiter.age.saturating_add(1);
+        }
+    }
+}
+
+impl Parking {
+    /// Wake up every parked thread to re-check its wait condition.
+    pub fn notify_all(&self) {
+        let _parking = self.lock.lock();
+        self.waiter.notify_all();
+    }
+}
+
+/// A counting semaphore admitting at most 'max' threads at a time.
+///
+/// ```
+/// use naive_semaphore::NaiveSemaphore;
+///
+/// let semaphore = NaiveSemaphore::new(2);
+///
+/// let permit = semaphore.wait();
+/// assert_eq!(semaphore.current_count(), 1);
+///
+/// drop(permit);
+/// assert_eq!(semaphore.current_count(), 0);
+/// ```
+pub struct NaiveSemaphore {
+    // The capacity and the count of currently running threads, changed without locking.
+    permits: Permits,
+    // Shared with the `CancelToken`s of threads parked in `wait_cancellable`.
+    parking: Arc<Parking>,
+    // The count of parked threads and registered tasks, see `release_n`.
+    waiters: Counter,
+    // Occupancy of the slot indices handed out by `wait_indexed`.
+    slots: Mutex<Slots>,
+    // The labels of the permits handed out by `wait_labeled` and not released yet.
+    labels: Mutex<Labels>,
+    observer: Option<Arc<dyn SemaphoreObserver>>,
+    events: Option<SyncSender<SemaphoreEvent>>,
+    stats: Option<StatsRecorder>,
+    histogram: Option<WaitHistogram>,
+    // Whether `release_n` rejects releasing more permits than are taken, see `strict`.
+    strict: bool,
+    // Whether parking a thread panics instead, see `non_blocking`.
+    non_blocking: bool,
+    // Whether releases wake up every parked thread, see `with_broadcast_on_release`.
+    broadcast: bool,
+    // How often a full `wait_for_slots` retries before parking, see `with_spin`.
+    spins: u32,
+    // How many threads may wait at a time before `wait_or_overloaded` rejects more.
+    max_waiters: usize,
+    // Set by `close`, only read without the lock by the fast path of `wait_or_closed`.
+    closed: AtomicBool,
+    // The count of permits taken for good by `SemaphorePermit::forget`, not outstanding.
+    forgotten: AtomicUsize,
+    // The count of permits marked with `SemaphorePermit::mark_active`.
+    active: AtomicUsize,
+    // The threads that acquired the outstanding guards, see `with_reentrancy_check`.
+    holders: Option<Mutex<Vec<ThreadId>>>,
+    // Set up by `with_deadlock_timeout`, checked by waiters parking for 'timeout' at a time.
+    watchdog: Option<DeadlockWatchdog>,
+    // Tasks waiting in `acquire`, only locked with `parking` held.
+    #[cfg(feature = "async")]
+    pub(crate) wakers: Mutex<WakerList>,
 }
 
-struct NaiveSemaphore {
-    max: usize,
-    is_locked: Mutex<bool>,
-    waiter: Condvar,
-    // The count of currently running threads.
-    current: Counter,
-}
+impl NaiveSemaphore {
+    /// Create a semaphore admitting at most 'max' threads at a time.
+    ///
+    /// # Panics
+    ///
+    /// Panics if 'max' is zero, see `try_new`.
+    pub fn new(max: usize) -> Self {
+        Self::try_new(max).expect("a semaphore needs a capacity of at least one")
+    }
+
+    /// Create a semaphore admitting at most 'max' threads at a time.
+    /// Fails for a 'max' of zero, since such a semaphore could never admit a thread.
+    pub fn try_new(max: usize) -> Result<Self, InvalidCapacity> {
+        if max == 0 {
+            return Err(InvalidCapacity);
+        }
+        Ok(Self {
+            permits: Permits::new(max),
+            parking: Arc::new(Parking {
+                lock: Mutex::new(Parked::default()),
+                waiter: Condvar::new(),
+                #[cfg(test)]
+                wakeups: AtomicUsize::new(0),
+            }),
+            waiters: Counter::new(0),
+            slots: Mutex::new(Slots::default()),
+            labels: Mutex::new(Labels::default()),
+            observer: None,
+            events: None,
+            stats: None,
+            histogram: None,
+            strict: false,
+            non_blocking: false,
+            broadcast: false,
+            spins: 0,
+            max_waiters: usize::MAX,
+            closed: AtomicBool::new(false),
+            forgotten: AtomicUsize::new(0),
+            active: AtomicUsize::new(0),
+            holders: None,
+            watchdog: None,
+            #[cfg(feature = "async")]
+            wakers: Mutex::new(WakerList::default()),
+        })
+    }
+
+    /// Like `new`, but collecting cumulative statistics, see `stats`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if 'max' is zero, see `try_new`.
+    pub fn with_stats(max: usize) -> Self {
+        let mut semaphore = Self::new(max);
+        semaphore.stats = Some(StatsRecorder::default());
+        semaphore
+    }
+
+    /// Like `new`, but collecting a histogram of how long each acquisition blocked, queried
+    /// with `wait_latency_percentile`. Acquisitions not blocking at all count with zero.
+    ///
+    /// # Panics
+    ///
+    /// Panics if 'max' is zero, see `try_new`.
+    pub fn with_wait_histogram(max: usize) -> Self {
+        let mut semaphore = Self::new(max);
+        semaphore.histogram = Some(WaitHistogram::new());
+        semaphore
+    }
+
+    /// Like `new`, but catching double releases: `release_one` and `release_n` never release
+    /// more permits than are currently taken, and debug builds panic on trying to.
+    /// Note that `release_all` and `reset` make releasing the permits still held an error.
+    ///
+    /// # Panics
+    ///
+    /// Panics if 'max' is zero, see `try_new`.
+    pub fn strict(max: usize) -> Self {
+        let mut semaphore = Self::new(max);
+        semaphore.strict = true;
+        semaphore
+    }
+
+    /// Like `new`, but remembering which thread acquired each `SemaphorePermit`, so that
+    /// `wait_or_would_deadlock` can refuse to block a thread that would wait for a permit it
+    /// holds itself. Guards moved to another thread still count for the acquiring one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if 'max' is zero, see `try_new`.
+    pub fn with_reentrancy_check(max: usize) -> Self {
+        let mut semaphore = Self::new(max);
+        semaphore.holders = Some(Mutex::new(Vec::new()));
+        semaphore
+    }
+
+    /// Like `new`, but starting with all 'max' permits taken, so `is_full` holds and every
+    /// `wait` blocks right away. The usage is inverted: whoever brings a resource online calls
+    /// `release_one` to make its permit available, e.g. one per connection established at
+    /// startup, and the workers get in gradually as capacity comes up. Permits never made
+    /// available count as outstanding once the semaphore is dropped, like after `wait_no_guard`.
+    ///
+    /// ```
+    /// use naive_semaphore::NaiveSemaphore;
+    ///
+    /// let semaphore = NaiveSemaphore::new_acquired(2);
+    /// assert!(semaphore.try_wait().is_none());
+    ///
+    /// // the first resource came online, then the second
+    /// semaphore.release_one();
+    /// assert!(semaphore.try_wait().is_some());
+    /// semaphore.release_one();
+    /// assert_eq!(semaphore.available_permits(), 2);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if 'max' is zero, see `try_new`, or exceeds `permits::MAX_TAKEN`.
+    pub fn new_acquired(max: usize) -> Self {
+        let semaphore = Self::new(max);
+        semaphore.permits.take(max);
+        semaphore
+    }
+
+    /// Like `new`, but for event loops that must never stall: every call that would park the
+    /// thread because no permit is free panics instead, e.g. `wait`, `wait_n` or
+    /// `wait_timeout`, so that accidental blocking shows up in tests rather than as a hung
+    /// reactor. Calls finding a free permit succeed as usual. Use `try_wait` or the futures
+    /// of the `async` feature, these never park.
+    ///
+    /// ```should_panic
+    /// use naive_semaphore::NaiveSemaphore;
+    ///
+    /// let semaphore = NaiveSemaphore::non_blocking(1);
+    /// let _held = semaphore.try_wait().unwrap();
+    /// assert!(semaphore.try_wait().is_none());
+    ///
+    /// // panics: "a non-blocking semaphore never parks a thread"
+    /// semaphore.wait();
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if 'max' is zero, see `try_new`.
+    pub fn non_blocking(max: usize) -> Self {
+        let mut semaphore = Self::new(max);
+        semaphore.non_blocking = true;
+        semaphore
+    }
+
+    /// Like `new`, but every release wakes up all parked threads to race for the freed
+    /// permits, instead of one thread per permit. This can raise the throughput of many
+    /// waiters holding permits for brief bursts only, as threads already woken take the next
+    /// free permit without waiting to be notified. The price is a thundering herd: all but the
+    /// winners re-check and park again, costing a context switch each, which grows with the
+    /// count of waiters. See the `notify` benchmark.
+    ///
+    /// # Panics
+    ///
+    /// Panics if 'max' is zero, see `try_new`.
+    pub fn with_broadcast_on_release(max: usize) -> Self {
+        let mut semaphore = Self::new(max);
+        semaphore.broadcast = true;
+        semaphore
+    }
+
+    /// Like `new`, but a `wait` finding no free permit first retries up to 'spins' times,
+    /// spinning exponentially longer in between, before it parks the thread. Cheaper than
+    /// parking if permits are held for microseconds only, a waste of CPU time otherwise.
+    ///
+    /// # Panics
+    ///
+    /// Panics if 'max' is zero, see `try_new`.
+    pub fn with_spin(max: usize, spins: u32) -> Self {
+        let mut semaphore = Self::new(max);
+        semaphore.spins = spins;
+        semaphore
+    }
+
+    /// Like `new`, but `wait_or_overloaded` rejects threads instead of letting them wait once
+    /// 'max_waiters' are already waiting. A 'max_waiters' of zero only admits while a permit
+    /// is free.
+    ///
+    /// # Panics
+    ///
+    /// Panics if 'max' is zero, see `try_new`.
+    pub fn with_max_waiters(max: usize, max_waiters: usize) -> Self {
+        let mut semaphore = Self::new(max);
+        semaphore.max_waiters = max_waiters;
+        semaphore
+    }
+
+    /// Like `new`, but reporting waits, acquisitions, releases and timeouts to 'observer'.
+    ///
+    /// # Panics
+    ///
+    /// Panics if 'max' is zero, see `try_new`.
+    pub fn with_observer(max: usize, observer: Arc<dyn SemaphoreObserver>) -> Self {
+        let mut semaphore = Self::new(max);
+        semaphore.observer = Some(observer);
+        semaphore
+    }
+
+    /// Like `new`, but watching for deadlocks: once a thread blocked in `wait`, `wait_n` or
+    /// `acquire_owned` sees every permit stay taken for 'timeout' without any release, the
+    /// semaphore emits a warning with the `tracing` or the `log` feature. Without either the
+    /// warning is compiled out, use `with_deadlock_handler` to report it some other way.
+    /// Typically every permit holder is itself blocked then, e.g. on a second permit. Each
+    /// stall is reported once.
+    ///
+    /// # Panics
+    ///
+    /// Panics if 'max' is zero, see `try_new`.
+    pub fn with_deadlock_timeout(max: usize, timeout: Duration) -> Self {
+        let mut semaphore = Self::new(max);
+        semaphore.watchdog = Some(DeadlockWatchdog::new(timeout, None));
+        semaphore
+    }
+
+    /// Like `new`, but streaming every wait, acquisition, release and timeout into 'events',
+    /// e.g. for debugging without a logging framework. Sending never blocks: events that do
+    /// not fit into the channel's buffer are dropped, so a slow consumer cannot stall the
+    /// semaphore.
+    ///
+    /// ```
+    /// use naive_semaphore::{NaiveSemaphore, SemaphoreEvent};
+    /// use std::sync::mpsc;
+    ///
+    /// let (sender, events) = mpsc::sync_channel(64);
+    /// let semaphore = NaiveSemaphore::with_event_sink(1, sender);
+    /// drop(semaphore.wait());
+    ///
+    /// assert!(matches!(events.try_recv(), Ok(SemaphoreEvent::Acquired { .. })));
+    /// assert!(matches!(events.try_recv(), Ok(SemaphoreEvent::Released { .. })));
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if 'max' is zero, see `try_new`.
+    pub fn with_event_sink(max: usize, events: SyncSender<SemaphoreEvent>) -> Self {
+        let mut semaphore = Self::new(max);
+        semaphore.events = Some(events);
+        semaphore
+    }
+
+    /// Like `with_deadlock_timeout`, but also calling 'on_deadlock' for every stall.
+    /// It runs on the blocked thread without any lock held, so it may use the semaphore,
+    /// e.g. call `release_all` to recover.
+    ///
+    /// # Panics
+    ///
+    /// Panics if 'max' is zero, see `try_new`.
+    pub fn with_deadlock_handler<F>(max: usize, timeout: Duration, on_deadlock: F) -> Self
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        let mut semaphore = Self::with_deadlock_timeout(max, timeout);
+        if let Some(watchdog) = &mut semaphore.watchdog {
+            watchdog.on_deadlock = Some(Box::new(on_deadlock));
+        }
+        semaphore
+    }
+
+    /// Start configuring a semaphore with several of the optional behaviors of the `with_*`
+    /// constructors at once, see `SemaphoreBuilder`.
+    pub fn builder() -> SemaphoreBuilder {
+        SemaphoreBuilder::default()
+    }
+
+    /// Create a semaphore with the capacity and occupancy of 'config', see `to_config`.
+    /// The restored 'current' permits are not held by any guard: like after `wait_no_guard`,
+    /// whoever took over the running threads releases them with `release_one`.
+    pub fn from_config(config: SemaphoreConfig) -> Self {
+        let semaphore = Self::new(config.max());
+        semaphore.permits.try_acquire(config.current());
+        semaphore
+    }
+
+    /// The current capacity and occupancy, a snapshot to restore with `from_config`.
+    pub fn to_config(&self) -> SemaphoreConfig {
+        let max = self.max_permits();
+        // a shrinking `set_max` may leave more running threads than the new capacity
+        let current = self.current_count().min(max);
+        SemaphoreConfig::new(max, current).expect("the capacity is at least one")
+    }
+
+    /// Move the semaphore into a cloneable `SemaphoreHandle` to share it between threads.
+    pub fn into_handle(self) -> SemaphoreHandle {
+        SemaphoreHandle::from(self)
+    }
+
+    /// The count of currently running threads.
+    ///
+    /// A single atomic read without the lock, so never blocking: the value was the exact count
+    /// at some point during the call, but may be outdated once returned. It stays within
+    /// `0..=max` (unless `set_max` just shrank the capacity below the running threads) and
+    /// counts the caller's own permits. Whether the semaphore is full is derived from the same
+    /// count, there is no separate state for a read to be inconsistent with.
+    pub fn current_count(&self) -> usize {
+        self.permits.current()
+    }
+
+    /// The count of permits marked as actively working with `SemaphorePermit::mark_active`,
+    /// as opposed to just held, e.g. by threads blocked on I/O. Permits are marked after
+    /// being taken and unmarked before being released, so the active permits are always
+    /// part of `current_count`. Like that one the value may be outdated once returned,
+    /// so two separate reads of both need not be consistent with each other.
+    pub fn active_count(&self) -> usize {
+        self.active.load(Ordering::Relaxed)
+    }
+
+    /// The count of threads that could start right now without blocking.
+    /// Derived from a single atomic read of the current count, so it is a point-in-time
+    /// snapshot that can be outdated as soon as it is returned.
+    pub fn available_permits(&self) -> usize {
+        self.max_permits().saturating_sub(self.current_count())
+    }
+
+    /// The share of the capacity in use, `current_count` over `max_permits` in `0.0..=1.0`,
+    /// e.g. for a gauge scraped by a metrics loop. Capped at 1.0 while more permits are taken
+    /// than a shrunk capacity allows, and 0.0 for a capacity of zero rather than NaN.
+    pub fn capacity_utilization(&self) -> f64 {
+        let max = self.max_permits();
+        if max == 0 {
+            return 0.0;
+        }
+        (self.current_count() as f64 / max as f64).min(1.0)
+    }
+
+    /// The count of threads blocked waiting for a permit, including pending `acquire` futures,
+    /// e.g. to detect sustained backpressure. A point-in-time snapshot like `current_count`.
+    pub fn waiting_count(&self) -> usize {
+        self.waiters.get()
+    }
+
+    /// The capacity, i.e. the count of threads admitted at most at a time.
+    pub fn max_permits(&self) -> usize {
+        self.permits.max()
+    }
+
+    /// Whether every permit is taken, so that `wait` would block right now.
+    pub fn is_full(&self) -> bool {
+        !self.permits.has_room(1)
+    }
+
+    /// Whether no permit is taken at all.
+    pub fn is_empty(&self) -> bool {
+        self.current_count() == 0
+    }
+
+    /// A snapshot of the acquisitions and blocked time so far,
+    /// `None` unless created with `with_stats`.
+    pub fn stats(&self) -> Option<SemaphoreStats> {
+        self.stats.as_ref().map(StatsRecorder::snapshot)
+    }
+
+    /// The wait not exceeded by 'percentile' percent of the acquisitions so far, e.g. `99.0`
+    /// for the p99, accurate to an eighth of it. `None` unless created with
+    /// `with_wait_histogram`, or before the first acquisition.
+    ///
+    /// # Panics
+    ///
+    /// Panics if 'percentile' is not within `0.0..=100.0`.
+    pub fn wait_latency_percentile(&self, percentile: f64) -> Option<Duration> {
+        assert!(
+            (0.0..=100.0).contains(&percentile),
+            "the percentile {} is not within 0.0..=100.0",
+            percentile
+        );
+        self.histogram.as_ref()?.percentile(percentile)
+    }
+
+    /// Release a waiting thread, reduce the current count. Returns whether a waiting thread
+    /// or task was woken up for the permit, rather than it just becoming free, e.g. to count
+    /// releases under backpressure. Decided under the lock from the count of parked threads,
+    /// the woken thread may still lose the permit to a newcomer though.
+    pub fn release_one(&self) -> bool {
+        self.release_waking(1)
+    }
+
+    /// Release 'n' permits at once, the current count never drops below zero.
+    /// Cheaper than 'n' calls to `release_one`, e.g. for a producer returning a whole batch:
+    /// a single update of the count and a single critical section waking up to 'n' waiters.
+    /// A semaphore created with `strict` releases nothing if fewer than 'n' permits are taken.
+    pub fn release_n(&self, n: usize) {
+        self.release_waking(n);
+    }
+
+    /// `release_n`, returning whether any waiter was woken up.
+    fn release_waking(&self, n: usize) -> bool {
+        if self.strict {
+            let released = self.permits.try_release(n);
+            debug_assert!(
+                released,
+                "released {} permit(s) while only {} are taken",
+                n,
+                self.permits.current()
+            );
+            return released && self.released(n);
+        }
+        let released = self.permits.release(n);
+        self.released(released)
+    }
+
+    /// Like `release_one`, but failing without releasing anything if no permit is taken.
+    pub fn try_release_one(&self) -> Result<(), OverRelease> {
+        self.try_release_n(1)
+    }
+
+    /// Like `release_n`, but failing without releasing anything if fewer than 'n' permits
+    /// are taken, e.g. to detect double releases in release builds.
+    pub fn try_release_n(&self, n: usize) -> Result<(), OverRelease> {
+        if !self.permits.try_release(n) {
+            return Err(OverRelease);
+        }
+        self.released(n);
+        Ok(())
+    }
+
+    /// Report and wake up waiters for 'released' permits just given back to `permits`.
+    /// Returns whether any waiter was woken up.
+    fn released(&self, released: usize) -> bool {
+        if released == 0 {
+            return false;
+        }
+        self.observe(Observed::Released(released));
+        #[cfg(feature = "tracing")]
+        tracing::trace!(
+            permits = released,
+            current_count = self.permits.current(),
+            "released"
+        );
+        #[cfg(feature = "log")]
+        log::debug!(
+            "released {} permit(s), current count {}",
+            released,
+            self.permits.current()
+        );
+        // Waiters count themselves in before their last check for room, so either that check
+        // sees the release above or the release sees the waiter and notifies it under the lock,
+        // which cannot happen between its check and parking. Reading the count as a
+        // read-modify-write makes one of the two read from the other, a load could miss the
+        // waiter even with `SeqCst` in loom's model, see `Counter`.
+        if self.waiters.get_latest() > 0 {
+            let parking = self.lock();
+            // Wake up waiting threads on every release: a second release before the first
+            // woken thread ran would otherwise leave a waiter parked next to a free slot.
+            self.wake_waiters(&parking, released)
+        } else {
+            false
+        }
+    }
+
+    /// Change the capacity while the semaphore is in use.
+    /// Growing wakes up waiting threads that now fit, shrinking never evicts running threads:
+    /// new threads are simply not admitted until the current count dropped below 'new_max'.
+    ///
+    /// # Panics
+    ///
+    /// Panics if 'new_max' is zero.
+    pub fn set_max(&self, new_max: usize) {
+        assert!(new_max > 0, "a semaphore needs a capacity of at least one");
+        let parking = self.lock();
+        let old_max = self.permits.set_max(new_max);
+        if new_max > old_max {
+            self.wake_waiters(&parking, new_max - old_max);
+        }
+    }
+
+    /// Increase the capacity by 'n', e.g. when returning a batch of resources to the pool,
+    /// so that up to 'n' waiting threads can proceed.
+    pub fn add_permits(&self, n: usize) {
+        if n == 0 {
+            return;
+        }
+        let parking = self.lock();
+        self.permits.add_max(n);
+        self.wake_waiters(&parking, n);
+    }
+
+    /// Consolidate 'other' into this semaphore, e.g. when resharding two pools into one:
+    /// the merged semaphore admits as many threads as both together, and the permits taken
+    /// in either carry over, to be released on the merged one.
+    ///
+    /// Both are consumed, so no guard or handle can refer to the originals anymore. Only
+    /// permits taken with `wait_no_guard` may be outstanding then. The merged semaphore keeps
+    /// the configuration of 'self', the one of 'other' is dropped.
+    ///
+    /// ```
+    /// use naive_semaphore::NaiveSemaphore;
+    ///
+    /// let (a, b) = (NaiveSemaphore::new(2), NaiveSemaphore::new(3));
+    /// b.wait_no_guard();
+    ///
+    /// let merged = a.merge(b);
+    /// assert_eq!(merged.max_permits(), 5);
+    /// assert_eq!(merged.current_count(), 1);
+    /// merged.release_one();
+    /// ```
+    pub fn merge(self, other: NaiveSemaphore) -> NaiveSemaphore {
+        let current = other.current_count();
+        // handed over, so dropping 'other' does not report them as outstanding
+        other.permits.release(current);
+        self.permits.add_max(other.max_permits());
+        self.permits.take(current);
+        self.forgotten.fetch_add(
+            other.forgotten.swap(0, Ordering::Relaxed),
+            Ordering::Relaxed,
+        );
+        self
+    }
+
+    /// Forcibly reset the current count to zero and wake up every waiting thread.
+    ///
+    /// This breaks the pairing of `wait` and `release_one`: permits still held are not
+    /// tracked anymore, and releasing them later only saturates at zero. Intended for
+    /// shutting down a worker pool, not for regular use.
+    pub fn release_all(&self) {
+        let parking = self.lock();
+        self.permits.release(usize::MAX);
+        self.forgotten.store(0, Ordering::Relaxed);
+        self.wake_waiters(&parking, usize::MAX);
+    }
+
+    /// Return to the state right after construction: no permits taken, no slot index in use,
+    /// and every waiting thread woken up to compete for the free permits again.
+    ///
+    /// Like `release_all`, calling this while threads still hold permits desyncs the
+    /// accounting. Intended for reusing one semaphore across tests or for reinitialization.
+    ///
+    /// An `IndexedPermit` held across the reset loses its slot: `wait_indexed` may hand out
+    /// the same index again while it is still held, so two holders share it until the old
+    /// permit is dropped. Dropping it leaves the slot of the new holder taken.
+    pub fn reset(&self) {
+        let mut slots = self.slots.lock();
+        // the permits of the previous generation no longer free any slot
+        slots.generation += 1;
+        slots.used.clear();
+        drop(slots);
+        self.closed.store(false, Ordering::Relaxed);
+        self.release_all();
+    }
+
+    /// Close the semaphore for shutdown: `wait_or_closed`, `wait_timeout` and `wait_until`
+    /// admit no more threads and the ones blocked in them give up with `AcquireError::Closed`.
+    /// Permits already held stay valid and are released as usual, and `reset` opens the
+    /// semaphore again.
+    ///
+    /// **Threads blocked in plain `wait` are never released by this**: `wait` cannot fail, so
+    /// they keep waiting for a permit like before. The other acquisitions are not affected
+    /// either. Shut down with `wait_or_closed` or `wait_timeout` on the thread side.
+    pub fn close(&self) {
+        // set before taking the lock, so a waiter either sees it before parking or is notified
+        self.closed.store(true, Ordering::Relaxed);
+        self.parking.notify_all();
+    }
+
+    /// Whether `close` was called since the semaphore was created or last `reset`.
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Relaxed)
+    }
+
+    /// Block a thread in case the current count exceeds 'max'.
+    /// The returned permit releases the slot again once it is dropped.
+    pub fn wait(&self) -> SemaphorePermit<'_> {
+        let waited = self.wait_for_slots(1);
+        self.permit(1, waited)
+    }
+
+    /// An endless iterator of permits, each `next` blocking like `wait_or_closed`, e.g. for a
+    /// producer loop handing one permit to each job. It ends once the semaphore was closed.
+    ///
+    /// ```
+    /// use naive_semaphore::NaiveSemaphore;
+    ///
+    /// let semaphore = NaiveSemaphore::new(2);
+    /// std::thread::scope(|s| {
+    ///     for permit in semaphore.permits().take(4) {
+    ///         s.spawn(move || drop(permit));
+    ///     }
+    /// });
+    /// ```
+    pub fn permits(&self) -> PermitIter<'_> {
+        PermitIter { semaphore: self }
+    }
+
+    /// Like `wait`, but the permit also carries a slot index in `0..max` that no other
+    /// indexed permit holds at the same time, e.g. to pin per-slot buffers.
+    /// The slot is returned to the pool together with the permit.
+    pub fn wait_indexed(&self) -> IndexedPermit<'_> {
+        self.indexed(self.wait(), None)
+    }
+
+    /// Like `wait_indexed`, but granting the slot 'preferred' if it is free, e.g. the one a
+    /// pinned worker held last so its per-slot buffers are still warm in its cache.
+    /// Any other free slot is granted otherwise, also if 'preferred' is not below `max`.
+    pub fn wait_prefer(&self, preferred: usize) -> IndexedPermit<'_> {
+        self.indexed(self.wait(), Some(preferred))
+    }
+
+    /// Pick a slot for 'permit', 'preferred' if it is in range and free.
+    fn indexed<'a>(
+        &'a self,
+        permit: SemaphorePermit<'a>,
+        preferred: Option<usize>,
+    ) -> IndexedPermit<'a> {
+        // Holding a permit guarantees a free slot: at most 'max' indexed permits are out.
+        let mut slots = self.slots.lock();
+        let used = &mut slots.used;
+        let preferred = preferred
+            .filter(|&slot| slot < self.max_permits() && !used.get(slot).copied().unwrap_or(false));
+        let slot = match preferred.or_else(|| used.iter().position(|used| !used)) {
+            Some(slot) => {
+                if slot >= used.len() {
+                    used.resize(slot + 1, false);
+                }
+                slot
+            }
+            None => {
+                used.push(false);
+                used.len() - 1
+            }
+        };
+        used[slot] = true;
+        let generation = slots.generation;
+        drop(slots);
+
+        IndexedPermit {
+            permit,
+            slot,
+            generation,
+        }
+    }
+
+    /// Like `wait`, but recording 'label' for the permit until it is released, e.g. the name
+    /// of the caller, to debug who is holding the permits with `outstanding_labels`.
+    pub fn wait_labeled(&self, label: String) -> LabeledPermit<'_> {
+        let permit = self.wait();
+        let mut labels = self.labels.lock();
+        let id = labels.next;
+        labels.next += 1;
+        labels.held.insert(id, label);
+        drop(labels);
+
+        LabeledPermit { permit, id }
+    }
+
+    /// The labels of the permits of `wait_labeled` currently held, in acquisition order.
+    /// Permits acquired otherwise carry no label and are not listed.
+    pub fn outstanding_labels(&self) -> Vec<String> {
+        self.labels.lock().held.values().cloned().collect()
+    }
+
+    /// Run 'f' while holding a permit. The permit is released even if 'f' panics,
+    /// the panic itself is propagated afterwards.
+    pub fn with_permit<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce() -> R,
+    {
+        let _permit = self.wait();
+        f()
+    }
+
+    /// Run 'f' with a scope for spawning threads like `std::thread::scope`, but with at most
+    /// 'max' of them running at a time. Returns once every spawned thread finished.
+    ///
+    /// ```
+    /// use naive_semaphore::NaiveSemaphore;
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    ///
+    /// let semaphore = NaiveSemaphore::new(2);
+    /// let done = AtomicUsize::new(0);
+    ///
+    /// semaphore.scope(|s| {
+    ///     for _ in 0..8 {
+    ///         s.spawn(|| done.fetch_add(1, Ordering::SeqCst));
+    ///     }
+    /// });
+    /// assert_eq!(done.load(Ordering::SeqCst), 8);
+    /// ```
+    pub fn scope<'env, F, T>(&'env self, f: F) -> T
+    where
+        F: for<'scope> FnOnce(SemaphoreScope<'scope, 'env>) -> T,
+    {
+        std::thread::scope(|scope| {
+            f(SemaphoreScope {
+                semaphore: self,
+                scope,
+            })
+        })
+    }
+
+    /// Acquire a permit without blocking the thread, for use from async tasks.
+    /// Dropping the future before it completes consumes no permit.
+    #[cfg(feature = "async")]
+    pub fn acquire(&self) -> Acquire<'_> {
+        Acquire::new(self)
+    }
+
+    /// Like `acquire`, but resolving to `Timeout` if no permit freed up within 'dur'.
+    /// Needs no particular runtime, the future is woken at the deadline by a timer thread of
+    /// its own. Dropping it before it completes consumes no permit either.
+    #[cfg(feature = "async")]
+    pub fn acquire_timeout(&self, dur: Duration) -> AcquireTimeout<'_> {
+        AcquireTimeout::new(self, Instant::now() + dur)
+    }
+
+    /// The building block of `acquire` for custom executors: take a permit if one is free,
+    /// otherwise register the task's waker and return `Poll::Pending`. The task is woken once
+    /// a permit may be free, and should then poll again with the same 'state'.
+    ///
+    /// Re-registering replaces an earlier waker of the same 'state', so only the waker passed
+    /// to the latest poll is woken. A task giving up before `Poll::Ready` must pass 'state' to
+    /// `cancel_acquire`, otherwise a release may be spent on waking it.
+    #[cfg(feature = "async")]
+    pub fn poll_acquire(
+        &self,
+        cx: &mut Context<'_>,
+        state: &mut AcquireState,
+    ) -> Poll<SemaphorePermit<'_>> {
+        let since = &mut state.since;
+        let id = &mut state.id;
+        if id.is_none() && self.permits.try_acquire(1) {
+            self.took_slots(1, Duration::ZERO);
+            return Poll::Ready(self.permit(1, Duration::ZERO));
+        }
+        let _parking = self.lock();
+        let mut wakers = self.wakers.lock();
+        let first_poll = id.is_none();
+        // register before checking for room, like `wait_for_slots`
+        if wakers.register(id, cx.waker()) {
+            self.waiters.add(1);
+        }
+        if self.permits.try_acquire(1) {
+            if wakers.deregister(id) {
+                self.waiters.sub(1);
+            }
+            drop(wakers);
+            let waited = since.map_or(Duration::ZERO, |since| since.elapsed());
+            self.took_slots(1, waited);
+            Poll::Ready(self.permit(1, waited))
+        } else {
+            if first_poll {
+                self.observe(Observed::WaitStarted);
+                *since = Some(Instant::now());
+            }
+            Poll::Pending
+        }
+    }
+
+    /// Remove the waker registered by `poll_acquire` for 'state', if any is left.
+    /// If the task was woken already but gives up before taking the permit, the wakeup is
+    /// passed on, so that no other waiter misses the release it was woken for.
+    #[cfg(feature = "async")]
+    pub fn cancel_acquire(&self, state: &mut AcquireState) {
+        if state.id.is_none() {
+            return;
+        }
+        let parking = self.lock();
+        if self.wakers.lock().deregister(&mut state.id) {
+            self.waiters.sub(1);
+        } else if self.permits.has_room(1) {
+            self.wake_waiters(&parking, 1);
+        }
+    }
+
+    /// Give up the acquisition of 'state' at its deadline, see `AcquireTimeout`.
+    #[cfg(feature = "async")]
+    pub(crate) fn acquire_timed_out(&self, state: &mut AcquireState) {
+        self.cancel_acquire(state);
+        self.observe(Observed::TimedOut);
+        self.record_blocked(state.since);
+        #[cfg(feature = "tracing")]
+        tracing::trace!(current_count = self.permits.current(), "timed out");
+    }
+
+    /// Like `wait`, but the returned permit keeps the semaphore alive on its own instead of
+    /// borrowing it, so it can be moved into a `'static` context such as a spawned thread.
+    pub fn acquire_owned(self: &Arc<Self>) -> OwnedSemaphorePermit {
+        self.wait_no_guard();
+        OwnedSemaphorePermit {
+            semaphore: Arc::clone(self),
+            permits: 1,
+        }
+    }
+
+    /// Like `acquire_owned`, but blocking until 'n' permits are available and taking them all
+    /// at once, like `wait_n`. Fails right away if 'n' exceeds 'max' instead of blocking
+    /// forever.
+    pub fn acquire_many_owned(
+        self: &Arc<Self>,
+        n: usize,
+    ) -> Result<OwnedSemaphorePermit, ExceedsCapacity> {
+        if n > self.max_permits() {
+            return Err(ExceedsCapacity);
+        }
+        if n > 0 {
+            self.wait_for_slots(n);
+        }
+        Ok(OwnedSemaphorePermit {
+            semaphore: Arc::clone(self),
+            permits: n,
+        })
+    }
+
+    /// Like `acquire_owned`, but only if a permit is immediately available, never blocks.
+    /// Takes no lock either way, like `try_wait`.
+    pub fn try_acquire_owned(self: &Arc<Self>) -> Option<OwnedSemaphorePermit> {
+        if !self.permits.try_acquire(1) {
+            return None;
+        }
+        self.took_slots(1, Duration::ZERO);
+        Some(OwnedSemaphorePermit {
+            semaphore: Arc::clone(self),
+            permits: 1,
+        })
+    }
+
+    /// Like `wait`, but without a guard: the caller is responsible for calling `release_one`.
+    pub fn wait_no_guard(&self) {
+        self.wait_for_slots(1);
+    }
+
+    /// Block a thread until 'n' permits are available and take them all at once.
+    /// The returned permit releases all 'n' again once it is dropped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if 'n' exceeds 'max', as such a request could never be satisfied.
+    pub fn wait_n(&self, n: usize) -> SemaphorePermit<'_> {
+        let max = self.max_permits();
+        assert!(
+            n <= max,
+            "cannot acquire {} permits from a semaphore with max {}",
+            n,
+            max
+        );
+        let waited = if n > 0 {
+            self.wait_for_slots(n)
+        } else {
+            Duration::ZERO
+        };
+        self.permit(n, waited)
+    }
+
+    /// Take 'n' permits, blocking until there is room. Returns how long it blocked.
+    fn wait_for_slots(&self, n: usize) -> Duration {
+        // fast path, no lock needed as long as there is room
+        if self.permits.try_acquire(n) || self.spin_for_slots(n) {
+            self.took_slots(n, Duration::ZERO);
+            return Duration::ZERO;
+        }
+        self.check_may_block();
+        self.block_for_slots(self.lock(), n)
+    }
+
+    /// The slow path of `wait_for_slots`, entered with the lock held.
+    fn block_for_slots<'a>(&'a self, mut parking: MutexGuard<'a, Parked>, n: usize) -> Duration {
+        // counted in before checking for room, see `release_n`
+        self.waiters.add(1);
+        let blocks = !self.permits.has_room(n);
+        if blocks {
+            self.observe(Observed::WaitStarted);
+        }
+        let blocked_since = blocks.then(Instant::now);
+        #[cfg(feature = "tracing")]
+        let _span = blocks.then(|| self.wait_span(n).entered());
+        let mut stall = self.watchdog.as_ref().map(DeadlockWatchdog::stall);
+        // `Condvar::wait` may return spuriously, re-check until there is actually room
+        while !self.permits.try_acquire(n) {
+            let timeout = self.watchdog.as_ref().map(|watchdog| watchdog.timeout);
+            parking = self.park(parking, n, timeout);
+            if let (Some(watchdog), Some(stall)) = (&self.watchdog, &mut stall) {
+                if self.is_full() && watchdog.is_deadlock(stall) {
+                    // the handler may use the semaphore, still counted as waiting meanwhile
+                    drop(parking);
+                    self.report_deadlock(watchdog);
+                    parking = self.lock();
+                }
+            }
+        }
+        self.waiters.sub(1);
+        drop(parking);
+        let waited = self.record_blocked(blocked_since);
+        self.took_slots(n, waited);
+        waited
+    }
+
+    /// Like `wait`, but once a permit frees up the waiter with the highest 'priority' is
+    /// admitted first, ties in arrival order. Every admission passing a waiter over raises
+    /// its priority by one, so low priorities still get in eventually.
+    ///
+    /// The priorities only order the callers of this method: `wait` and the other
+    /// acquisitions do not queue up and may take a free permit first.
+    pub fn wait_with_priority(&self, priority: u8) -> SemaphorePermit<'_> {
+        let mut parking = self.lock();
+        let ticket = parking.enqueue(priority);
+        let waited = self
+            .wait_for_turn(parking, ticket, None)
+            .expect("waiting without a deadline never times out");
+        self.permit(1, waited)
+    }
+
+    /// Queue up in the admission order of `wait_with_priority` with the lowest priority,
+    /// i.e. behind everyone already queued, and return a handle telling the place in line,
+    /// e.g. to show "3rd in line" while waiting with `QueuedWait::wait_timeout`.
+    ///
+    /// The handle holds its place until it waits or is dropped, and nobody behind it is
+    /// admitted while it is at the head of the queue, so wait right after queuing up.
+    ///
+    /// ```
+    /// use naive_semaphore::NaiveSemaphore;
+    /// use std::time::Duration;
+    ///
+    /// let semaphore = NaiveSemaphore::new(1);
+    /// let mut queued = semaphore.wait_queued();
+    /// let permit = loop {
+    ///     match queued.wait_timeout(Duration::from_millis(100)) {
+    ///         Ok(permit) => break permit,
+    ///         Err(still_queued) => {
+    ///             println!("{} ahead", still_queued.position());
+    ///             queued = still_queued;
+    ///         }
+    ///     }
+    /// };
+    /// ```
+    pub fn wait_queued(&self) -> QueuedWait<'_> {
+        let ticket = self.lock().enqueue(0);
+        QueuedWait {
+            semaphore: self,
+            ticket,
+        }
+    }
+
+    /// Block until 'ticket' is the head of the queue and a permit is free, then take it and
+    /// leave the queue. Returns how long that blocked, or `None` once 'deadline' passed,
+    /// with the ticket still queued.
+    fn wait_for_turn<'a>(
+        &'a self,
+        mut parking: MutexGuard<'a, Parked>,
+        ticket: u64,
+        deadline: Option<Instant>,
+    ) -> Option<Duration> {
+        if self.non_blocking {
+            // a single attempt, refused before counting in as waiting
+            if parking.head() != Some(ticket) || !self.permits.try_acquire(1) {
+                // the ticket must not hold up everyone queued behind it
+                parking.leave(ticket);
+                drop(parking);
+                refuse_to_block();
+            }
+            parking.dequeue(ticket);
+            drop(parking);
+            self.took_slots(1, Duration::ZERO);
+            return Some(Duration::ZERO);
+        }
+        self.waiters.add(1);
+        let blocks = parking.head() != Some(ticket) || !self.permits.has_room(1);
+        if blocks {
+            self.observe(Observed::WaitStarted);
+        }
+        let blocked_since = blocks.then(Instant::now);
+        #[cfg(feature = "tracing")]
+        let _span = blocks.then(|| self.wait_span(1).entered());
+        while parking.head() != Some(ticket) || !self.permits.try_acquire(1) {
+            let remaining =
+                deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+            if remaining.is_some_and(|remaining| remaining.is_zero()) {
+                self.waiters.sub(1);
+                drop(parking);
+                self.observe(Observed::TimedOut);
+                self.record_blocked(blocked_since);
+                return None;
+            }
+            parking = self.park(parking, 1, remaining);
+        }
+        parking.dequeue(ticket);
+        self.waiters.sub(1);
+        if !parking.by_priority.is_empty() {
+            // the next in line may fit as well
+            self.parking.waiter.notify_all();
+        }
+        drop(parking);
+        let waited = self.record_blocked(blocked_since);
+        self.took_slots(1, waited);
+        Some(waited)
+    }
+
+    /// Like `wait`, but calling 'on_wake' each time the blocked thread wakes up, also
+    /// spuriously, before it re-checks for a free permit, e.g. to update a progress spinner.
+    /// The lock is not held meanwhile, so 'on_wake' may use the semaphore as well.
+    pub fn wait_with(&self, mut on_wake: impl FnMut()) -> SemaphorePermit<'_> {
+        if let Some(permit) = self.try_wait() {
+            return permit;
+        }
+        self.check_may_block();
+        let mut parking = self.lock();
+        self.waiters.add(1);
+        let blocks = !self.permits.has_room(1);
+        if blocks {
+            self.observe(Observed::WaitStarted);
+        }
+        let blocked_since = blocks.then(Instant::now);
+        #[cfg(feature = "tracing")]
+        let _span = blocks.then(|| self.wait_span(1).entered());
+        while !self.permits.try_acquire(1) {
+            parking = self.park(parking, 1, None);
+            // still counted as waiting, and checking for room before parking again
+            drop(parking);
+            on_wake();
+            parking = self.lock();
+        }
+        self.waiters.sub(1);
+        drop(parking);
+        let waited = self.record_blocked(blocked_since);
+        self.took_slots(1, waited);
+        self.permit(1, waited)
+    }
+
+    /// Block a thread for at most 'dur' in case the current count exceeds 'max'.
+    /// On timeout the current count is left untouched.
+    ///
+    /// Fails with `AcquireError::TimedOut` once 'dur' passed, or with `AcquireError::Closed`
+    /// once the semaphore was closed, including while the thread is blocked, see `close`.
+    pub fn wait_timeout(&self, dur: Duration) -> Result<SemaphorePermit<'_>, AcquireError> {
+        self.wait_until(Instant::now() + dur)
+    }
+
+    /// Like `wait_timeout`, but waiting for 'base' varied randomly by up to 'jitter_fraction'
+    /// of it in either direction, e.g. `0.1` for ±10%. Clients timing out and retrying with
+    /// the same 'base' drift apart instead of retrying in lockstep.
+    ///
+    /// # Panics
+    ///
+    /// Panics if 'jitter_fraction' is not within `0.0..=1.0`.
+    pub fn wait_timeout_jittered(
+        &self,
+        base: Duration,
+        jitter_fraction: f64,
+    ) -> Result<SemaphorePermit<'_>, AcquireError> {
+        assert!(
+            (0.0..=1.0).contains(&jitter_fraction),
+            "the jitter fraction {} is not within 0.0..=1.0",
+            jitter_fraction
+        );
+        self.wait_timeout(jittered(base, jitter_fraction, random_unit()))
+    }
+
+    /// Like `wait_timeout`, but returning `None` on timeout or once closed, e.g. for
+    /// `if let Some(permit)`.
+    pub fn try_wait_for(&self, dur: Duration) -> Option<SemaphorePermit<'_>> {
+        self.wait_timeout(dur).ok()
+    }
+
+    /// Like `wait_timeout`, but blocking until 'deadline' at most, e.g. to share one deadline
+    /// between several acquisitions. A deadline already passed only takes a free permit.
+    pub fn wait_until(&self, deadline: Instant) -> Result<SemaphorePermit<'_>, AcquireError> {
+        if self.is_closed() {
+            return Err(AcquireError::Closed);
+        }
+        if let Some(permit) = self.try_wait() {
+            return Ok(permit);
+        }
+        self.check_may_block();
+        let mut parking = self.lock();
+        self.waiters.add(1);
+        let blocks = !self.permits.has_room(1);
+        if blocks {
+            self.observe(Observed::WaitStarted);
+        }
+        let blocked_since = blocks.then(Instant::now);
+        #[cfg(feature = "tracing")]
+        let _span = blocks.then(|| self.wait_span(1).entered());
+        while !self.permits.try_acquire(1) {
+            if self.is_closed() {
+                self.waiters.sub(1);
+                drop(parking);
+                self.record_blocked(blocked_since);
+                #[cfg(feature = "tracing")]
+                tracing::trace!(current_count = self.permits.current(), "closed");
+                return Err(AcquireError::Closed);
+            }
+            // recompute what is left, a spurious wakeup must not restart the full 'dur'
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                self.waiters.sub(1);
+                drop(parking);
+                self.observe(Observed::TimedOut);
+                self.record_blocked(blocked_since);
+                #[cfg(feature = "tracing")]
+                tracing::trace!(current_count = self.permits.current(), "timed out");
+                return Err(AcquireError::TimedOut);
+            }
+            parking = self.park(parking, 1, Some(remaining));
+        }
+        self.waiters.sub(1);
+        drop(parking);
+        let waited = self.record_blocked(blocked_since);
+        self.took_slots(1, waited);
+        Ok(self.permit(1, waited))
+    }
+
+    /// Block a thread until a permit is free or 'token' is cancelled, whichever comes first.
+    /// Cancelling wakes the thread up right away with `AcquireError::Cancelled`, and the
+    /// cancelled path takes no permit.
+    pub fn wait_cancellable(
+        &self,
+        token: &CancelToken,
+    ) -> Result<SemaphorePermit<'_>, AcquireError> {
+        if token.is_cancelled() {
+            return Err(AcquireError::Cancelled);
+        }
+        if let Some(permit) = self.try_wait() {
+            return Ok(permit);
+        }
+        self.check_may_block();
+        // registered before checking the token, so a concurrent `cancel` notifies this thread
+        token.register(&self.parking);
+        let mut parking = self.lock();
+        self.waiters.add(1);
+        let blocks = !self.permits.has_room(1);
+        if blocks {
+            self.observe(Observed::WaitStarted);
+        }
+        let blocked_since = blocks.then(Instant::now);
+        #[cfg(feature = "tracing")]
+        let _span = blocks.then(|| self.wait_span(1).entered());
+        let admitted = loop {
+            if token.is_cancelled() {
+                break false;
+            }
+            if self.permits.try_acquire(1) {
+                break true;
+            }
+            parking = self.park(parking, 1, None);
+        };
+        self.waiters.sub(1);
+        drop(parking);
+        token.deregister(&self.parking);
+        let waited = self.record_blocked(blocked_since);
+
+        if !admitted {
+            #[cfg(feature = "tracing")]
+            tracing::trace!(current_count = self.permits.current(), "cancelled");
+            return Err(AcquireError::Cancelled);
+        }
+        self.took_slots(1, waited);
+        Ok(self.permit(1, waited))
+    }
+
+    /// Like `wait`, but failing with `AcquireError::Closed` instead of admitting the thread
+    /// once the semaphore was closed, including while the thread is blocked, see `close`.
+    pub fn wait_or_closed(&self) -> Result<SemaphorePermit<'_>, AcquireError> {
+        if self.is_closed() {
+            return Err(AcquireError::Closed);
+        }
+        if let Some(permit) = self.try_wait() {
+            return Ok(permit);
+        }
+        self.check_may_block();
+        let mut parking = self.lock();
+        self.waiters.add(1);
+        let blocks = !self.permits.has_room(1);
+        if blocks {
+            self.observe(Observed::WaitStarted);
+        }
+        let blocked_since = blocks.then(Instant::now);
+        #[cfg(feature = "tracing")]
+        let _span = blocks.then(|| self.wait_span(1).entered());
+        let admitted = loop {
+            if self.is_closed() {
+                break false;
+            }
+            if self.permits.try_acquire(1) {
+                break true;
+            }
+            parking = self.park(parking, 1, None);
+        };
+        self.waiters.sub(1);
+        drop(parking);
+        let waited = self.record_blocked(blocked_since);
+
+        if !admitted {
+            #[cfg(feature = "tracing")]
+            tracing::trace!(current_count = self.permits.current(), "closed");
+            return Err(AcquireError::Closed);
+        }
+        self.took_slots(1, waited);
+        Ok(self.permit(1, waited))
+    }
+
+    /// Like `wait`, but failing with `AcquireError::Overloaded` right away instead of queuing
+    /// up once the `max_waiters` of `with_max_waiters` threads are already waiting, e.g. to
+    /// shed load instead of building up an ever growing backlog. All waiting threads count
+    /// towards the bound, also the ones blocked in `wait`, which itself never rejects a thread.
+    pub fn wait_or_overloaded(&self) -> Result<SemaphorePermit<'_>, AcquireError> {
+        if let Some(permit) = self.try_wait() {
+            return Ok(permit);
+        }
+        self.check_may_block();
+        let parking = self.lock();
+        // waiters only come and go under the lock, so no other thread can slip in meanwhile
+        if self.waiters.get() >= self.max_waiters && !self.permits.has_room(1) {
+            drop(parking);
+            #[cfg(feature = "tracing")]
+            tracing::trace!(waiters = self.waiters.get(), "overloaded");
+            return Err(AcquireError::Overloaded);
+        }
+        let waited = self.block_for_slots(parking, 1);
+        Ok(self.permit(1, waited))
+    }
+
+    /// Like `wait`, but failing with `AcquireError::WouldDeadlock` instead of blocking if the
+    /// calling thread already holds a permit and none is free. Such a wait never ends when the
+    /// semaphore is full of the thread's own permits, e.g. after a re-entrant call. Only a
+    /// semaphore created with `with_reentrancy_check` knows the holders, any other just waits.
+    ///
+    /// ```
+    /// use naive_semaphore::{AcquireError, NaiveSemaphore};
+    ///
+    /// let semaphore = NaiveSemaphore::with_reentrancy_check(1);
+    /// let _held = semaphore.wait();
+    /// assert!(matches!(semaphore.wait_or_would_deadlock(), Err(AcquireError::WouldDeadlock)));
+    /// ```
+    pub fn wait_or_would_deadlock(&self) -> Result<SemaphorePermit<'_>, AcquireError> {
+        if let Some(permit) = self.try_wait() {
+            return Ok(permit);
+        }
+        self.check_may_block();
+        let parking = self.lock();
+        if let Some(holders) = &self.holders {
+            let thread = std::thread::current().id();
+            if !self.permits.has_room(1) && holders.lock().contains(&thread) {
+                drop(parking);
+                #[cfg(feature = "tracing")]
+                tracing::trace!(current_count = self.permits.current(), "would deadlock");
+                return Err(AcquireError::WouldDeadlock);
+            }
+        }
+        let waited = self.block_for_slots(parking, 1);
+        Ok(self.permit(1, waited))
+    }
+
+    /// Acquire a permit only if one is immediately available, never blocks.
+    /// Check and increment are a single compare-and-swap, so concurrent callers cannot both
+    /// see room and overshoot 'max', and no lock is taken either way.
+    pub fn try_wait(&self) -> Option<SemaphorePermit<'_>> {
+        if !self.permits.try_acquire(1) {
+            return None;
+        }
+        self.took_slots(1, Duration::ZERO);
+        Some(self.permit(1, Duration::ZERO))
+    }
+
+    /// Like `wait_n`, but only if all 'n' permits are immediately available, never blocks.
+    /// Either all 'n' are taken in a single compare-and-swap or none at all, e.g. for batch
+    /// work where fewer than 'n' permits are of no use.
+    pub fn try_acquire_n(&self, n: usize) -> Option<SemaphorePermit<'_>> {
+        if n > 0 {
+            if !self.permits.try_acquire(n) {
+                return None;
+            }
+            self.took_slots(n, Duration::ZERO);
+        }
+        Some(self.permit(n, Duration::ZERO))
+    }
+
+    /// Retry taking 'n' permits for the `spins` of `with_spin`, backing off exponentially.
+    fn spin_for_slots(&self, n: usize) -> bool {
+        const MAX_BACKOFF: u32 = 64;
+        let mut backoff = 1;
+        for _ in 0..self.spins {
+            for _ in 0..backoff {
+                std::hint::spin_loop();
+            }
+            if self.permits.try_acquire(n) {
+                return true;
+            }
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        }
+        false
+    }
+
+    /// Like `try_wait`, but failing with an `AcquireError`: `WouldBlock` if no permit is free,
+    /// `Closed` once the semaphore was closed like `wait_or_closed`.
+    pub fn try_acquire(&self) -> Result<SemaphorePermit<'_>, AcquireError> {
+        if self.is_closed() {
+            return Err(AcquireError::Closed);
+        }
+        self.try_wait().ok_or(AcquireError::WouldBlock)
+    }
+
+    /// Refuse to block on a `non_blocking` semaphore. Every blocking acquisition checks this
+    /// as soon as taking a free permit failed, before it registers anywhere or counts itself
+    /// as waiting, so that the panicking call leaves nothing behind.
+    fn check_may_block(&self) {
+        if self.non_blocking {
+            refuse_to_block();
+        }
+    }
+
+    /// Block on `waiter` for at most 'timeout', counted as a thread waiting for 'n' permits.
+    fn park<'a>(
+        &self,
+        mut parked: MutexGuard<'a, Parked>,
+        n: usize,
+        timeout: Option<Duration>,
+    ) -> MutexGuard<'a, Parked> {
+        debug_assert!(
+            !self.non_blocking,
+            "parking on a non-blocking semaphore, see `check_may_block`"
+        );
+        parked.threads += 1;
+        if n > 1 {
+            parked.bulk += 1;
+        }
+        parked = match timeout {
+            Some(timeout) => self.parking.waiter.wait_timeout(parked, timeout),
+            None => self.parking.waiter.wait(parked),
+        };
+        parked.threads -= 1;
+        if n > 1 {
+            parked.bulk -= 1;
+        }
+        #[cfg(test)]
+        self.parking.wakeups.fetch_add(1, Ordering::SeqCst);
+        parked
+    }
+
+    /// Wake up waiting threads after 'freed' permits became available.
+    /// Each single-permit waiter takes at most one freed permit, so waking more than 'freed'
+    /// of them only has the rest re-check and park again. A single notify may hit a bulk
+    /// waiter that still lacks room or a priority waiter not first in line though, so everyone
+    /// re-checks while any of those are parked, as with `with_broadcast_on_release`.
+    ///
+    /// Returns whether any thread or task was woken up.
+    fn wake_waiters(&self, parked: &MutexGuard<'_, Parked>, freed: usize) -> bool {
+        if let Some(watchdog) = &self.watchdog {
+            watchdog.wakeups.add(1);
+        }
+        let woken = if self.broadcast || parked.bulk > 0 || !parked.by_priority.is_empty() {
+            self.parking.waiter.notify_all();
+            parked.threads
+        } else {
+            let woken = freed.min(parked.threads);
+            for _ in 0..woken {
+                self.parking.waiter.notify_one();
+            }
+            woken
+        };
+        #[cfg(feature = "async")]
+        let woken = {
+            let tasks = self.wakers.lock().wake(freed);
+            self.waiters.sub(tasks);
+            woken + tasks
+        };
+        woken > 0
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Parked> {
+        self.parking.lock.lock()
+    }
+
+    /// Account for 'n' permits just taken by `Permits::try_acquire` after blocking for 'waited'.
+    fn took_slots(&self, n: usize, waited: Duration) {
+        self.observe(Observed::Acquired(n));
+        if let Some(stats) = &self.stats {
+            stats.record_acquisition();
+        }
+        if let Some(histogram) = &self.histogram {
+            histogram.record(waited);
+        }
+        #[cfg(feature = "tracing")]
+        tracing::trace!(
+            permits = n,
+            current_count = self.permits.current(),
+            "acquired"
+        );
+        #[cfg(feature = "log")]
+        log::debug!(
+            "acquired {} permit(s), current count {}",
+            n,
+            self.permits.current()
+        );
+    }
+
+    fn report_deadlock(&self, watchdog: &DeadlockWatchdog) {
+        #[cfg(feature = "tracing")]
+        tracing::warn!(
+            max = self.max_permits(),
+            waiters = self.waiters.get(),
+            timeout = ?watchdog.timeout,
+            "possible deadlock: every permit stayed taken with threads waiting"
+        );
+        #[cfg(feature = "log")]
+        log::warn!(
+            "possible deadlock: all {} permit(s) stayed taken for {:?} with {} thread(s) waiting",
+            self.max_permits(),
+            watchdog.timeout,
+            self.waiters.get()
+        );
+        if let Some(on_deadlock) = &watchdog.on_deadlock {
+            on_deadlock();
+        }
+    }
+
+    /// Record the time blocked since 'blocked_since', if at all, and return it.
+    fn record_blocked(&self, blocked_since: Option<Instant>) -> Duration {
+        let blocked = blocked_since.map_or(Duration::ZERO, |since| since.elapsed());
+        if let (Some(stats), Some(_)) = (&self.stats, blocked_since) {
+            stats.record_blocked(blocked);
+        }
+        blocked
+    }
+
+    /// A guard for 'permits' just taken after blocking for 'waited'.
+    fn permit(&self, permits: usize, waited: Duration) -> SemaphorePermit<'_> {
+        let holder = self.holders.as_ref().map(|holders| {
+            let thread = std::thread::current().id();
+            holders.lock().push(thread);
+            thread
+        });
+        SemaphorePermit {
+            semaphore: self,
+            permits,
+            waited,
+            acquired_at: Instant::now(),
+            active: false,
+            holder,
+        }
+    }
+
+    fn add_holder(&self, holder: ThreadId) {
+        if let Some(holders) = &self.holders {
+            holders.lock().push(holder);
+        }
+    }
+
+    /// Forget one guard of 'holder', if it was recorded at all.
+    fn remove_holder(&self, holder: Option<ThreadId>) {
+        if let (Some(holders), Some(holder)) = (&self.holders, holder) {
+            let mut holders = holders.lock();
+            if let Some(idx) = holders.iter().position(|thread| *thread == holder) {
+                holders.swap_remove(idx);
+            }
+        }
+    }
+
+    fn observe(&self, event: Observed) {
+        if let Some(observer) = &self.observer {
+            match event {
+                Observed::WaitStarted => observer.on_wait_start(),
+                Observed::Acquired(permits) => observer.on_acquired(permits),
+                Observed::Released(permits) => observer.on_released(permits),
+                Observed::TimedOut => observer.on_timeout(),
+            }
+        }
+        if let Some(events) = &self.events {
+            let at = Instant::now();
+            let current_count = self.permits.current();
+            let event = match event {
+                Observed::WaitStarted => SemaphoreEvent::WaitStarted { at, current_count },
+                Observed::Acquired(permits) => SemaphoreEvent::Acquired {
+                    at,
+                    current_count,
+                    permits,
+                },
+                Observed::Released(permits) => SemaphoreEvent::Released {
+                    at,
+                    current_count,
+                    permits,
+                },
+                Observed::TimedOut => SemaphoreEvent::TimedOut { at, current_count },
+            };
+            // a full or disconnected channel only loses the event, it never blocks
+            let _ = events.try_send(event);
+        }
+    }
+
+    /// The span entered while a thread is blocked waiting for 'n' permits.
+    #[cfg(feature = "tracing")]
+    fn wait_span(&self, n: usize) -> tracing::Span {
+        tracing::trace_span!(
+            "semaphore_wait",
+            permits = n,
+            current_count = self.permits.current()
+        )
+    }
+}
+
+/// The panic of a blocking acquisition on a `NaiveSemaphore::non_blocking` semaphore.
+fn refuse_to_block() -> ! {
+    panic!("a non-blocking semaphore never parks a thread, use `try_wait` instead");
+}
+
+/// 'base' scaled by a factor in `1 - fraction..1 + fraction`, picked by 'unit' in `0.0..1.0`.
+fn jittered(base: Duration, fraction: f64, unit: f64) -> Duration {
+    base.mul_f64(1.0 + fraction * (2.0 * unit - 1.0))
+}
+
+/// A pseudo-random number in `0.0..1.0`, from a xorshift generator per thread.
+/// Good enough to spread timeouts, and seeded from `RandomState` to differ between threads.
+fn random_unit() -> f64 {
+    use std::cell::Cell;
+    use std::hash::{BuildHasher, Hasher};
+
+    thread_local! {
+        static STATE: Cell<u64> = Cell::new(
+            std::collections::hash_map::RandomState::new().build_hasher().finish() | 1
+        );
+    }
+    STATE.with(|state| {
+        let mut x = state.get();
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        state.set(x);
+        // the upper 53 bits fill the mantissa of an f64 exactly
+        (x >> 11) as f64 / (1u64 << 53) as f64
+    })
+}
+
+/// One or more permits acquired from a `NaiveSemaphore`, released when dropped (also on panic).
+///
+/// It only borrows the semaphore, so a semaphore on the stack can be shared with the threads
+/// of a `std::thread::scope` without an `Arc`, and a permit can be moved into one of them to
+/// be released there.
+#[must_use = "the permit is released immediately if it is not held"]
+pub struct SemaphorePermit<'a> {
+    semaphore: &'a NaiveSemaphore,
+    permits: usize,
+    waited: Duration,
+    acquired_at: Instant,
+    // Whether the permits count towards `active_count`, see `mark_active`.
+    active: bool,
+    // The acquiring thread, recorded with `NaiveSemaphore::with_reentrancy_check`.
+    holder: Option<ThreadId>,
+}
+
+impl<'a> SemaphorePermit<'a> {
+    /// The count of permits held by this guard.
+    pub fn permits(&self) -> usize {
+        self.permits
+    }
+
+    /// How long the acquisition blocked before the permits were taken,
+    /// zero if they were free right away.
+    pub fn wait_duration(&self) -> Duration {
+        self.waited
+    }
+
+    /// How long the permits have been held so far.
+    pub fn held_duration(&self) -> Duration {
+        self.acquired_at.elapsed()
+    }
+
+    /// Split the guard into one single-permit guard per permit it holds, e.g. to hand the
+    /// permits of a `wait_n` to sub-tasks releasing them independently.
+    pub fn split(mut self) -> Vec<SemaphorePermit<'a>> {
+        // the emptied guard releases nothing when dropped, the new ones own the permits
+        let permits = std::mem::replace(&mut self.permits, 0);
+        let active = std::mem::replace(&mut self.active, false);
+        (0..permits)
+            .map(|_| SemaphorePermit {
+                semaphore: self.semaphore,
+                permits: 1,
+                waited: self.waited,
+                acquired_at: self.acquired_at,
+                // each part takes over its share of the active count
+                active,
+                // and is held by the same thread, the emptied guard drops its own record
+                holder: self
+                    .holder
+                    .inspect(|&holder| self.semaphore.add_holder(holder)),
+            })
+            .collect()
+    }
+
+    /// Take over the permits of 'other', the inverse of `split`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if 'other' was acquired from another semaphore.
+    pub fn merge(&mut self, mut other: SemaphorePermit<'a>) {
+        assert!(
+            std::ptr::eq(self.semaphore, other.semaphore),
+            "cannot merge permits of different semaphores"
+        );
+        // the merged guard stays active or idle as this one was
+        other.mark_idle();
+        let active = self.active;
+        self.mark_idle();
+        self.permits += std::mem::replace(&mut other.permits, 0);
+        if active {
+            self.mark_active();
+        }
+    }
+
+    /// Count the permits of this guard towards `NaiveSemaphore::active_count` until
+    /// `mark_idle` or until they are released, e.g. once the holder starts its actual work
+    /// instead of blocking on I/O.
+    pub fn mark_active(&mut self) {
+        if !self.active {
+            self.active = true;
+            self.semaphore
+                .active
+                .fetch_add(self.permits, Ordering::Relaxed);
+        }
+    }
+
+    /// Stop counting the permits of this guard as active, see `mark_active`.
+    pub fn mark_idle(&mut self) {
+        if self.active {
+            self.active = false;
+            self.semaphore
+                .active
+                .fetch_sub(self.permits, Ordering::Relaxed);
+        }
+    }
+
+    /// Move the permits of this guard over to 'other', e.g. a unit of work passing from one
+    /// pipeline stage's pool to the next: blocks until 'other' has room for all of them while
+    /// still holding them here, and only then releases them here. So the unit is always
+    /// counted by at least one of the semaphores, never by neither. The new guard keeps the
+    /// active state of this one.
+    ///
+    /// ```
+    /// use naive_semaphore::NaiveSemaphore;
+    ///
+    /// let (parse, store) = (NaiveSemaphore::new(4), NaiveSemaphore::new(2));
+    /// let parsing = parse.wait();
+    /// let storing = parsing.transfer_to(&store);
+    /// assert_eq!((parse.current_count(), store.current_count()), (0, 1));
+    /// assert_eq!(storing.permits(), 1);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if the guard holds more permits than the capacity of 'other', see `wait_n`.
+    pub fn transfer_to<'b>(self, other: &'b NaiveSemaphore) -> SemaphorePermit<'b> {
+        let mut transferred = other.wait_n(self.permits);
+        if self.active {
+            transferred.mark_active();
+        }
+        drop(self);
+        transferred
+    }
+
+    /// Drop the guard without releasing its permits, e.g. when the resource behind them died.
+    /// This permanently reduces how many threads the semaphore admits at a time, unless made
+    /// up for with `add_permits`. Only `release_all` and `reset` bring the permits back.
+    pub fn forget(mut self) {
+        self.mark_idle();
+        self.semaphore.remove_holder(self.holder);
+        self.semaphore
+            .forgotten
+            .fetch_add(self.permits, Ordering::Relaxed);
+        std::mem::forget(self);
+    }
+}
+
+impl Drop for SemaphorePermit<'_> {
+    fn drop(&mut self) {
+        // no longer active before the permits are released, see `active_count`
+        self.mark_idle();
+        self.semaphore.remove_holder(self.holder);
+        self.semaphore.release_n(self.permits);
+    }
+}
+
+/// Configures a `NaiveSemaphore` with any combination of the behaviors of the `with_*`
+/// constructors, created with `NaiveSemaphore::builder`. Each method corresponds to the
+/// constructor of the same name, only 'max' is required. `build` rejects options that do
+/// not go together, e.g. `on_deadlock` without `deadlock_timeout`.
+///
+/// Fair admission in arrival order is a different semaphore, `fair` switches over to
+/// configuring a `FairSemaphore` instead. Not covered on purpose: `new_acquired` and
+/// `from_config` set up the state or read a `SemaphoreConfig` rather than add a behavior,
+/// and priorities are picked per call with `wait_with_priority`, not per semaphore.
+///
+/// ```
+/// use naive_semaphore::NaiveSemaphore;
+///
+/// let semaphore = NaiveSemaphore::builder()
+///     .max(4)
+///     .strict()
+///     .spin(8)
+///     .stats()
+///     .build()
+///     .unwrap();
+/// drop(semaphore.wait());
+/// assert_eq!(semaphore.stats().unwrap().total_acquisitions, 1);
+///
+/// assert!(NaiveSemaphore::builder().build().is_err());
+/// ```
+#[derive(Default)]
+#[must_use = "a builder does nothing unless `build` is called"]
+pub struct SemaphoreBuilder {
+    max: usize,
+    strict: bool,
+    non_blocking: bool,
+    broadcast_on_release: bool,
+    reentrancy_check: bool,
+    spins: u32,
+    max_waiters: Option<usize>,
+    observer: Option<Arc<dyn SemaphoreObserver>>,
+    events: Option<SyncSender<SemaphoreEvent>>,
+    stats: bool,
+    histogram: bool,
+    deadlock_timeout: Option<Duration>,
+    on_deadlock: Option<Box<dyn Fn() + Send + Sync>>,
+}
+
+impl SemaphoreBuilder {
+    /// Admit at most 'max' threads at a time.
+    pub fn max(mut self, max: usize) -> Self {
+        self.max = max;
+        self
+    }
+
+    /// Catch double releases, see `NaiveSemaphore::strict`.
+    pub fn strict(mut self) -> Self {
+        self.strict = true;
+        self
+    }
+
+    /// Panic instead of parking a thread, see `NaiveSemaphore::non_blocking`.
+    pub fn non_blocking(mut self) -> Self {
+        self.non_blocking = true;
+        self
+    }
+
+    /// Wake up every parked thread on release, see `NaiveSemaphore::with_broadcast_on_release`.
+    pub fn broadcast_on_release(mut self) -> Self {
+        self.broadcast_on_release = true;
+        self
+    }
+
+    /// Remember the acquiring threads, see `NaiveSemaphore::with_reentrancy_check`.
+    pub fn reentrancy_check(mut self) -> Self {
+        self.reentrancy_check = true;
+        self
+    }
+
+    /// Retry up to 'spins' times before parking, see `NaiveSemaphore::with_spin`.
+    pub fn spin(mut self, spins: u32) -> Self {
+        self.spins = spins;
+        self
+    }
+
+    /// Reject waiters beyond 'max_waiters', see `NaiveSemaphore::with_max_waiters`.
+    pub fn max_waiters(mut self, max_waiters: usize) -> Self {
+        self.max_waiters = Some(max_waiters);
+        self
+    }
+
+    /// Report to 'observer', see `NaiveSemaphore::with_observer`.
+    pub fn observer(mut self, observer: Arc<dyn SemaphoreObserver>) -> Self {
+        self.observer = Some(observer);
+        self
+    }
+
+    /// Stream events into 'events', see `NaiveSemaphore::with_event_sink`.
+    pub fn event_sink(mut self, events: SyncSender<SemaphoreEvent>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Collect statistics, see `NaiveSemaphore::with_stats`.
+    pub fn stats(mut self) -> Self {
+        self.stats = true;
+        self
+    }
+
+    /// Collect a histogram of the waits, see `NaiveSemaphore::with_wait_histogram`.
+    pub fn wait_histogram(mut self) -> Self {
+        self.histogram = true;
+        self
+    }
+
+    /// Watch for deadlocks, see `NaiveSemaphore::with_deadlock_timeout`.
+    pub fn deadlock_timeout(mut self, timeout: Duration) -> Self {
+        self.deadlock_timeout = Some(timeout);
+        self
+    }
+
+    /// Call 'on_deadlock' for every stall, see `NaiveSemaphore::with_deadlock_handler`.
+    /// Requires `deadlock_timeout`, `build` fails without it.
+    pub fn on_deadlock<F>(mut self, on_deadlock: F) -> Self
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        self.on_deadlock = Some(Box::new(on_deadlock));
+        self
+    }
+
+    /// Admit threads strictly in arrival order, configuring a `FairSemaphore` from here on.
+    /// It supports none of the other options, its `build` fails if any of them were set.
+    pub fn fair(self) -> FairSemaphoreBuilder {
+        let unsupported = self.strict
+            || self.non_blocking
+            || self.broadcast_on_release
+            || self.reentrancy_check
+            || self.spins > 0
+            || self.max_waiters.is_some()
+            || self.observer.is_some()
+            || self.events.is_some()
+            || self.stats
+            || self.histogram
+            || self.deadlock_timeout.is_some()
+            || self.on_deadlock.is_some();
+        FairSemaphoreBuilder::new(self.max, unsupported)
+    }
+
+    /// Create the configured semaphore. Fails if 'max' is zero or was never set, for
+    /// `on_deadlock` without `deadlock_timeout`, and for `non_blocking` together with
+    /// `deadlock_timeout`, since no thread ever blocks to be watched.
+    pub fn build(self) -> Result<NaiveSemaphore, InvalidConfig> {
+        let contradicting = (self.on_deadlock.is_some() && self.deadlock_timeout.is_none())
+            || (self.non_blocking && self.deadlock_timeout.is_some());
+        if contradicting {
+            return Err(InvalidConfig);
+        }
+        let mut semaphore = NaiveSemaphore::try_new(self.max).map_err(|_| InvalidConfig)?;
+        semaphore.strict = self.strict;
+        semaphore.non_blocking = self.non_blocking;
+        semaphore.broadcast = self.broadcast_on_release;
+        semaphore.holders = self.reentrancy_check.then(|| Mutex::new(Vec::new()));
+        semaphore.spins = self.spins;
+        if let Some(max_waiters) = self.max_waiters {
+            semaphore.max_waiters = max_waiters;
+        }
+        semaphore.observer = self.observer;
+        semaphore.events = self.events;
+        semaphore.stats = self.stats.then(StatsRecorder::default);
+        semaphore.histogram = self.histogram.then(WaitHistogram::new);
+        semaphore.watchdog = self
+            .deadlock_timeout
+            .map(|timeout| DeadlockWatchdog::new(timeout, self.on_deadlock));
+        Ok(semaphore)
+    }
+}
+
+impl Default for NaiveSemaphore {
+    /// One permit per available CPU core, or a single permit if that cannot be determined.
+    fn default() -> Self {
+        let max = std::thread::available_parallelism().map_or(1, |cores| cores.get());
+        Self::new(max)
+    }
+}
+
+impl From<usize> for NaiveSemaphore {
+    /// A semaphore admitting at most 'max' threads at a time, see `new`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if 'max' is zero.
+    fn from(max: usize) -> Self {
+        Self::new(max)
+    }
+}
+
+impl fmt::Debug for NaiveSemaphore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Only atomics are read, so formatting never blocks on a lock the caller may hold.
+        // Each is read once to keep the printed values consistent with each other.
+        let max = self.max_permits();
+        let current = self.current_count();
+        f.debug_struct("NaiveSemaphore")
+            .field("max", &max)
+            .field("current_count", &current)
+            .field("available_permits", &max.saturating_sub(current))
+            .field("locked", &(current >= max))
+            .field("waiters", &self.waiters.get())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for NaiveSemaphore {
+    fn drop(&mut self) {
+        // Outstanding permits at this point were taken with `wait_no_guard` and never released,
+        // which points to a forgotten `release_one`, e.g. on an early return.
+        // Only checked in debug builds, and not while unwinding to avoid a double panic.
+        let outstanding = self
+            .permits
+            .current()
+            .saturating_sub(self.forgotten.load(Ordering::Relaxed));
+        debug_assert!(
+            std::thread::panicking() || outstanding == 0,
+            "NaiveSemaphore dropped with {} outstanding permit(s)",
+            outstanding
+        );
+    }
+}
+
+/// The iterator returned by `NaiveSemaphore::permits`.
+pub struct PermitIter<'a> {
+    semaphore: &'a NaiveSemaphore,
+}
+
+impl<'a> Iterator for PermitIter<'a> {
+    type Item = SemaphorePermit<'a>;
+
+    fn next(&mut self) -> Option<SemaphorePermit<'a>> {
+        self.semaphore.wait_or_closed().ok()
+    }
+}
+
+/// A `SemaphorePermit` that also holds a slot index, see `NaiveSemaphore::wait_indexed`.
+#[must_use = "the permit is released immediately if it is not held"]
+pub struct IndexedPermit<'a> {
+    permit: SemaphorePermit<'a>,
+    slot: usize,
+    // The `Slots::generation` the slot was taken in.
+    generation: u64,
+}
+
+impl IndexedPermit<'_> {
+    /// The slot index in `0..max` held by this permit.
+    pub fn slot(&self) -> usize {
+        self.slot
+    }
+}
+
+impl Drop for IndexedPermit<'_> {
+    fn drop(&mut self) {
+        // free the slot first, the permit itself is released right after when dropping the field
+        let mut slots = self.permit.semaphore.slots.lock();
+        if slots.generation == self.generation {
+            slots.used[self.slot] = false;
+        }
+    }
+}
+
+/// The slot indices of `wait_indexed`, whether each is in use, grown on demand.
+#[derive(Default)]
+struct Slots {
+    // Bumped by `reset`, the permits taken before no longer own their slot.
+    generation: u64,
+    used: Vec<bool>,
+}
+
+/// The labels of `wait_labeled`, by the id of their permit.
+#[derive(Default)]
+struct Labels {
+    next: u64,
+    held: BTreeMap<u64, String>,
+}
+
+/// A `SemaphorePermit` that also holds a label, see `NaiveSemaphore::wait_labeled`.
+#[must_use = "the permit is released immediately if it is not held"]
+pub struct LabeledPermit<'a> {
+    permit: SemaphorePermit<'a>,
+    id: u64,
+}
+
+impl Drop for LabeledPermit<'_> {
+    fn drop(&mut self) {
+        // remove the label first, the permit itself is released right after when dropping the field
+        self.permit.semaphore.labels.lock().held.remove(&self.id);
+    }
+}
+
+/// A place in the admission queue of a `NaiveSemaphore`, see `NaiveSemaphore::wait_queued`.
+/// Dropping it gives up the place.
+#[must_use = "the place in line is given up immediately if it is not held"]
+pub struct QueuedWait<'a> {
+    semaphore: &'a NaiveSemaphore,
+    ticket: u64,
+}
+
+impl<'a> QueuedWait<'a> {
+    /// How many queued threads are admitted before this one, zero once it is next in line.
+    pub fn position(&self) -> usize {
+        self.semaphore.lock().position(self.ticket)
+    }
+
+    /// Block until it is this one's turn and a permit is free.
+    pub fn wait(self) -> SemaphorePermit<'a> {
+        let semaphore = self.semaphore;
+        let waited = semaphore
+            .wait_for_turn(semaphore.lock(), self.ticket, None)
+            .expect("waiting without a deadline never times out");
+        // admitted, the place in line is gone already
+        std::mem::forget(self);
+        semaphore.permit(1, waited)
+    }
+
+    /// Like `wait`, but blocking for at most 'dur'. On timeout the place in line is kept
+    /// and handed back, e.g. to report the `position` before waiting on.
+    pub fn wait_timeout(self, dur: Duration) -> Result<SemaphorePermit<'a>, Self> {
+        let semaphore = self.semaphore;
+        match semaphore.wait_for_turn(semaphore.lock(), self.ticket, Some(Instant::now() + dur)) {
+            Some(waited) => {
+                std::mem::forget(self);
+                Ok(semaphore.permit(1, waited))
+            }
+            None => Err(self),
+        }
+    }
+}
+
+impl Drop for QueuedWait<'_> {
+    fn drop(&mut self) {
+        let mut parking = self.semaphore.lock();
+        parking.leave(self.ticket);
+        if !parking.by_priority.is_empty() {
+            // the next in line may have been held up behind this one
+            self.semaphore.parking.waiter.notify_all();
+        }
+    }
+}
+
+/// Like `SemaphorePermit`, but owning a reference-counted semaphore, see `acquire_owned`.
+///
+/// The permit is `Send`, so the thread acquiring it need not be the one releasing it: it can
+/// be handed to a worker, e.g. through a channel, and is released exactly once by whoever
+/// drops it last. A permit dropped inside an undelivered message is released as well.
+///
+/// ```
+/// use naive_semaphore::NaiveSemaphore;
+/// use std::sync::{mpsc, Arc};
+///
+/// let semaphore = Arc::new(NaiveSemaphore::new(1));
+/// let (jobs, inbox) = mpsc::channel();
+/// let worker = std::thread::spawn(move || {
+///     for (job, permit) in inbox {
+///         println!("working on {}", job);
+///         drop(permit);
+///     }
+/// });
+///
+/// jobs.send(("job-1", semaphore.acquire_owned())).unwrap();
+/// // blocks until the worker dropped the permit of the first job
+/// jobs.send(("job-2", semaphore.acquire_owned())).unwrap();
+/// drop(jobs);
+/// worker.join().unwrap();
+/// assert_eq!(semaphore.current_count(), 0);
+/// ```
+#[must_use = "the permit is released immediately if it is not held"]
+pub struct OwnedSemaphorePermit {
+    semaphore: Arc<NaiveSemaphore>,
+    permits: usize,
+}
+
+impl OwnedSemaphorePermit {
+    /// The count of permits held by this guard.
+    pub fn permits(&self) -> usize {
+        self.permits
+    }
+}
+
+impl Drop for OwnedSemaphorePermit {
+    fn drop(&mut self) {
+        self.semaphore.release_n(self.permits);
+    }
+}
+
+/// A scope to spawn threads in, each holding a permit, see `NaiveSemaphore::scope`.
+/// Cheap to copy, it only borrows the semaphore and the underlying `std::thread::Scope`.
+#[derive(Clone, Copy)]
+pub struct SemaphoreScope<'scope, 'env: 'scope> {
+    semaphore: &'env NaiveSemaphore,
+    scope: &'scope std::thread::Scope<'scope, 'env>,
+}
+
+impl<'scope, 'env> SemaphoreScope<'scope, 'env> {
+    /// Wait for a permit, then spawn a scoped thread running 'f' which releases the permit
+    /// once done. Blocks the calling thread while all permits are taken, so there are never
+    /// more than 'max' threads of this scope alive.
+    pub fn spawn<F, T>(&self, f: F) -> ScopedJoinHandle<'scope, T>
+    where
+        F: FnOnce() -> T + Send + 'scope,
+        T: Send + 'scope,
+    {
+        let permit = self.semaphore.wait();
+        self.scope.spawn(move || {
+            let _permit = permit;
+            f()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::collection::vec;
+    use proptest::prelude::*;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+    use stopwatch::Stopwatch;
+
+    fn aggregate_counts<'a>(
+        hm: &'a mut HashMap<char, usize>,
+        update_hm: &HashMap<char, usize>,
+    ) -> &'a HashMap<char, usize> {
+        for (c, ct) in update_hm.iter() {
+            if let Some(current_ct) = hm.get_mut(c) {
+                *current_ct += ct;
+            } else {
+                hm.insert(*c, *ct);
+            }
+        }
+        hm
+    }
+
+    fn char_frequency(word: &str) -> HashMap<char, usize> {
+        let mut hm = HashMap::new();
+        for c in word.chars() {
+            if let Some(ct) = hm.get_mut(&c) {
+                *ct += 1;
+            } else {
+                hm.insert(c, 1);
+            }
+        }
+        hm
+    }
+
+    /// Where `frequency` reports the progress of its threads.
+    trait Logger: Send + Sync {
+        fn log(&self, message: &str);
+    }
+
+    impl<F: Fn(&str) + Send + Sync> Logger for F {
+        fn log(&self, message: &str) {
+            self(message)
+        }
+    }
+
+    /// The default of `frequency`, keeping `cargo test` quiet.
+    struct NoLogger;
+
+    impl Logger for NoLogger {
+        fn log(&self, _message: &str) {}
+    }
+
+    fn frequency(input: &'static [&str], worker_count: usize, slow_down: Option<u64>) -> usize {
+        frequency_logged(input, worker_count, slow_down, Arc::new(NoLogger))
+    }
+
+    // TODO: check with spans and jaeger?
+    fn frequency_logged(
+        input: &'static [&str],
+        worker_count: usize,
+        slow_down: Option<u64>,
+        logger: Arc<dyn Logger>,
+    ) -> usize {
+        let naive_semaphore = Arc::new(NaiveSemaphore::new(worker_count));
+        let mut handles = vec![];
+
+        let results: HashMap<char, usize> = HashMap::new();
+        let sharedstate_res = Arc::new(Mutex::new(results));
+
+        for (idx, word) in input.iter().enumerate() {
+            let semaphore = Arc::clone(&naive_semaphore);
+            let ss_res = Arc::clone(&sharedstate_res);
+            let logger = Arc::clone(&logger);
+
+            let handle = std::thread::spawn(move || {
+                let sw = Stopwatch::start_new();
+                logger.log(&format!(
+                    "thread {} - cc {}: waiting",
+                    idx,
+                    semaphore.current_count()
+                ));
+
+                let permit = semaphore.wait();
+
+                logger.log(&format!(
+                    "thread {} - cc {} - {} ms: starting work",
+                    idx,
+                    semaphore.current_count(),
+                    sw.elapsed_ms()
+                ));
+
+                // just as a test to simulate a heavier workload
+                if let Some(ms) = slow_down {
+                    std::thread::sleep(std::time::Duration::from_millis(ms));
+                }
+
+                let word_count = char_frequency(word);
+
+                {
+                    let res_lock = &mut ss_res.lock().unwrap();
+                    aggregate_counts(res_lock, &word_count);
+                }
+
+                logger.log(&format!(
+                    "thread {} - cc {} - {} ms: work done",
+                    idx,
+                    semaphore.current_count(),
+                    sw.elapsed_ms()
+                ));
+
+                drop(permit);
+                logger.log(&format!(
+                    "thread {} - cc {} - {} ms: released",
+                    idx,
+                    semaphore.current_count(),
+                    sw.elapsed_ms()
+                ));
+            });
+
+            handles.push(handle);
+        }
+
+        let num_threads = handles.len();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        logger.log(&format!("{:?}", sharedstate_res.lock().unwrap()));
+        num_threads
+    }
+
+    #[test]
+    fn test_scoped_threads_borrow_the_semaphore() {
+        let semaphore = NaiveSemaphore::strict(2);
+        let running = AtomicUsize::new(0);
+        let peak = AtomicUsize::new(0);
+
+        std::thread::scope(|s| {
+            for _ in 0..8 {
+                s.spawn(|| {
+                    let _permit = semaphore.wait();
+                    let now_running = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    peak.fetch_max(now_running, Ordering::SeqCst);
+                    std::thread::sleep(Duration::from_millis(2));
+                    running.fetch_sub(1, Ordering::SeqCst);
+                });
+            }
+            // a permit acquired here is released by the scoped thread it moves into
+            let permit = semaphore.wait_n(2);
+            s.spawn(move || drop(permit));
+        });
+
+        assert!(peak.load(Ordering::SeqCst) <= 2);
+        assert_eq!(semaphore.current_count(), 0);
+    }
+
+    #[test]
+    fn test_outstanding_labels() {
+        let semaphore = NaiveSemaphore::new(3);
+        let first = semaphore.wait_labeled("first".to_string());
+        let second = semaphore.wait_labeled("second".to_string());
+        let _unlabeled = semaphore.wait();
+        assert_eq!(semaphore.outstanding_labels(), ["first", "second"]);
+
+        drop(first);
+        assert_eq!(semaphore.outstanding_labels(), ["second"]);
+        drop(second);
+        assert!(semaphore.outstanding_labels().is_empty());
+        assert_eq!(semaphore.current_count(), 1);
+    }
+
+    #[test]
+    fn test_scope_frequency() {
+        let worker_count = 5;
+        let semaphore = NaiveSemaphore::new(worker_count);
+        let results = Mutex::new(HashMap::new());
+        let running = AtomicUsize::new(0);
+        let peak = AtomicUsize::new(0);
+
+        semaphore.scope(|s| {
+            for word in ODE_AN_DIE_FREUDE.iter() {
+                s.spawn(|| {
+                    let now_running = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    peak.fetch_max(now_running, Ordering::SeqCst);
+
+                    let word_count = char_frequency(word);
+                    aggregate_counts(&mut results.lock().unwrap(), &word_count);
+                    std::thread::sleep(Duration::from_millis(1));
+                    running.fetch_sub(1, Ordering::SeqCst);
+                });
+            }
+        });
+
+        let mut expected = HashMap::new();
+        for word in ODE_AN_DIE_FREUDE.iter() {
+            aggregate_counts(&mut expected, &char_frequency(word));
+        }
+        assert_eq!(*results.lock().unwrap(), expected);
+        assert!(peak.load(Ordering::SeqCst) <= worker_count);
+        assert_eq!(semaphore.current_count(), 0);
+    }
+
+    // Poem by Friedrich Schiller. The corresponding music is the European Anthem.
+    const ODE_AN_DIE_FREUDE: [&str; 32] = [
+        "Freude schöner Götterfunken",
+        "Tochter aus Elysium,",
+        "Wir betreten feuertrunken,",
+        "Himmlische, dein Heiligtum!",
+        "Deine Zauber binden wieder",
+        "Was die Mode streng geteilt;",
+        "Alle Menschen werden Brüder,",
+        "Wo dein sanfter Flügel weilt.",
+        "Freude schöner Götterfunken",
+        "Tochter aus Elysium,",
+        "Wir betreten feuertrunken,",
+        "Himmlische, dein Heiligtum!",
+        "Deine Zauber binden wieder",
+        "Was die Mode streng geteilt;",
+        "Alle Menschen werden Brüder,",
+        "Wo dein sanfter Flügel weilt.",
+        "Freude schöner Götterfunken",
+        "Tochter aus Elysium,",
+        "Wir betreten feuertrunken,",
+        "Himmlische, dein Heiligtum!",
+        "Deine Zauber binden wieder",
+        "Was die Mode streng geteilt;",
+        "Alle Menschen werden Brüder,",
+        "Wo dein sanfter Flügel weilt.",
+        "Freude schöner Götterfunken",
+        "Tochter aus Elysium,",
+        "Wir betreten feuertrunken,",
+        "Himmlische, dein Heiligtum!",
+        "Deine Zauber binden wieder",
+        "Was die Mode streng geteilt;",
+        "Alle Menschen werden Brüder,",
+        "Wo dein sanfter Flügel weilt.",
+    ];
+
+    #[test]
+    fn test_permit_released_on_panic() {
+        let semaphore = Arc::new(NaiveSemaphore::new(1));
+
+        let sem = Arc::clone(&semaphore);
+        let result = std::thread::spawn(move || {
+            let _permit = sem.wait();
+            panic!("worker failed while holding a permit");
+        })
+        .join();
+
+        assert!(result.is_err());
+        assert_eq!(semaphore.current_count(), 0);
+
+        // the slot is available again
+        let _permit = semaphore.wait();
+        assert_eq!(semaphore.current_count(), 1);
+    }
+
+    #[test]
+    fn test_try_new() {
+        assert_eq!(NaiveSemaphore::try_new(0).err(), Some(InvalidCapacity));
+        assert!(NaiveSemaphore::try_new(1).is_ok());
+    }
+
+    #[test]
+    #[should_panic(expected = "capacity of at least one")]
+    fn test_new_panics_on_zero() {
+        NaiveSemaphore::new(0);
+    }
+
+    #[test]
+    fn test_available_permits() {
+        let max = 3;
+        let semaphore = NaiveSemaphore::new(max);
+        assert_eq!(semaphore.available_permits(), max);
+
+        let _first = semaphore.wait();
+        let _second = semaphore.wait();
+        assert_eq!(semaphore.available_permits(), 1);
+        assert_eq!(
+            semaphore.available_permits() + semaphore.current_count(),
+            max
+        );
+    }
+
+    #[test]
+    fn test_new_acquired() {
+        let semaphore = NaiveSemaphore::new_acquired(3);
+        assert_eq!(semaphore.available_permits(), 0);
+        assert!(semaphore.is_full());
+
+        for available in 1..=3 {
+            semaphore.release_one();
+            assert_eq!(semaphore.available_permits(), available);
+        }
+        assert!(!semaphore.is_full());
+        assert_eq!(semaphore.wait_n(3).permits(), 3);
+    }
+
+    #[test]
+    fn test_capacity_utilization() {
+        let semaphore = NaiveSemaphore::new(4);
+        assert_eq!(semaphore.capacity_utilization(), 0.0);
+
+        let half = semaphore.wait_n(2);
+        assert_eq!(semaphore.capacity_utilization(), 0.5);
+        let _rest = semaphore.wait_n(2);
+        assert_eq!(semaphore.capacity_utilization(), 1.0);
+
+        // more permits taken than the shrunk capacity allows
+        semaphore.set_max(2);
+        assert_eq!(semaphore.capacity_utilization(), 1.0);
+        drop(half);
+        assert_eq!(semaphore.capacity_utilization(), 1.0);
+    }
+
+    #[test]
+    fn test_wait_n() {
+        let semaphore = Arc::new(NaiveSemaphore::new(4));
+
+        let batch = semaphore.wait_n(3);
+        assert_eq!(batch.permits(), 3);
+        assert_eq!(semaphore.current_count(), 3);
+
+        let single = semaphore.wait();
+        assert!(semaphore.try_wait().is_none());
+
+        let sem = Arc::clone(&semaphore);
+        let waiting = std::thread::spawn(move || {
+            let permit = sem.wait_n(2);
+            permit.permits()
+        });
+
+        // releasing the single permit is not enough for the bulk waiter
+        drop(single);
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(!waiting.is_finished());
+
+        drop(batch);
+        assert_eq!(waiting.join().unwrap(), 2);
+        assert_eq!(semaphore.current_count(), 0);
+    }
+
+    #[test]
+    fn test_wait_n_zero_is_a_no_op() {
+        let semaphore = NaiveSemaphore::new(1);
+        let empty = semaphore.wait_n(0);
+        assert_eq!(empty.permits(), 0);
+        assert_eq!(semaphore.current_count(), 0);
+
+        let _permit = semaphore.wait();
+        drop(empty);
+        assert_eq!(semaphore.current_count(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot acquire 3 permits")]
+    fn test_wait_n_exceeding_max_panics() {
+        let semaphore = NaiveSemaphore::new(2);
+        let _permit = semaphore.wait_n(3);
+    }
+
+    #[test]
+    fn test_release_n_wakes_single_waiters() {
+        let semaphore = Arc::new(NaiveSemaphore::new(2));
+        let batch = semaphore.wait_n(2);
+
+        let waiting: Vec<_> = (0..2)
+            .map(|_| {
+                let sem = Arc::clone(&semaphore);
+                std::thread::spawn(move || {
+                    let permit = sem.wait();
+                    std::thread::sleep(Duration::from_millis(20));
+                    drop(permit);
+                })
+            })
+            .collect();
+
+        std::thread::sleep(Duration::from_millis(20));
+        drop(batch);
+        for handle in waiting {
+            handle.join().unwrap();
+        }
+        assert_eq!(semaphore.current_count(), 0);
+    }
+
+    #[test]
+    fn test_release_n_admits_n_waiters_at_once() {
+        let n = 4;
+        let semaphore = Arc::new(NaiveSemaphore::new(n));
+        semaphore.wait_for_slots(n);
+        // every waiter holds its permit until all of them got in
+        let barrier = Arc::new(std::sync::Barrier::new(n));
+
+        let handles: Vec<_> = (0..n)
+            .map(|_| {
+                let semaphore = Arc::clone(&semaphore);
+                let barrier = Arc::clone(&barrier);
+                std::thread::spawn(move || {
+                    let _permit = semaphore.wait();
+                    barrier.wait();
+                })
+            })
+            .collect();
+        while semaphore.waiting_count() < n {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+
+        semaphore.release_n(n);
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(semaphore.current_count(), 0);
+    }
+
+    #[test]
+    fn test_broadcast_on_release_wakes_everyone() {
+        let semaphore = Arc::new(NaiveSemaphore::with_broadcast_on_release(1));
+        let held = semaphore.wait();
+
+        let waiters = 4;
+        let handles: Vec<_> = (0..waiters)
+            .map(|_| {
+                let semaphore = Arc::clone(&semaphore);
+                std::thread::spawn(move || drop(semaphore.wait()))
+            })
+            .collect();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while semaphore.lock().threads < waiters {
+            assert!(Instant::now() < deadline, "the waiters never parked");
+            std::thread::sleep(Duration::from_millis(1));
+        }
+
+        // a single freed permit wakes every waiter, the losers park again
+        drop(held);
+        while semaphore.parking.wakeups.load(Ordering::SeqCst) < waiters {
+            assert!(Instant::now() < deadline, "not every waiter was woken");
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(semaphore.current_count(), 0);
+    }
+
+    #[test]
+    fn test_wakeups_bounded_by_freed_permits() {
+        let semaphore = Arc::new(NaiveSemaphore::new(2));
+        let held = semaphore.wait_n(2);
+        let (admitted_tx, admitted_rx) = std::sync::mpsc::channel();
+
+        let waiters = 8;
+        let handles: Vec<_> = (0..waiters)
+            .map(|_| {
+                let semaphore = Arc::clone(&semaphore);
+                let admitted_tx = admitted_tx.clone();
+                std::thread::spawn(move || {
+                    semaphore.wait_no_guard();
+                    admitted_tx.send(()).unwrap();
+                })
+            })
+            .collect();
+        while semaphore.waiting_count() < waiters {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+
+        semaphore.add_permits(3);
+        for _ in 0..3 {
+            admitted_rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        }
+        std::thread::sleep(Duration::from_millis(20));
+        // only the admitted threads woke up, the others stayed parked
+        assert_eq!(semaphore.parking.wakeups.load(Ordering::SeqCst), 3);
+        assert!(admitted_rx.try_recv().is_err());
+
+        drop(held);
+        semaphore.release_n(3);
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        semaphore.release_all();
+    }
+
+    #[test]
+    fn test_release_all_wakes_every_waiter() {
+        let max = 2;
+        let semaphore = Arc::new(NaiveSemaphore::new(max));
+        for _ in 0..max {
+            semaphore.wait_no_guard();
+        }
+
+        let parked: Vec<_> = (0..max)
+            .map(|_| {
+                let sem = Arc::clone(&semaphore);
+                std::thread::spawn(move || sem.wait_no_guard())
+            })
+            .collect();
+
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(parked.iter().all(|handle| !handle.is_finished()));
+
+        semaphore.release_all();
+        for handle in parked {
+            handle.join().unwrap();
+        }
+        assert_eq!(semaphore.current_count(), max);
+        semaphore.release_all();
+    }
+
+    #[test]
+    fn test_no_waiter_stays_parked_next_to_a_free_slot() {
+        let max = 2;
+        let workers = 8;
+        let semaphore = Arc::new(NaiveSemaphore::new(max));
+        let (done_tx, done_rx) = std::sync::mpsc::channel();
+
+        for _ in 0..workers {
+            let semaphore = Arc::clone(&semaphore);
+            let done_tx = done_tx.clone();
+            std::thread::spawn(move || {
+                for _ in 0..500 {
+                    let _permit = semaphore.wait();
+                    std::thread::yield_now();
+                }
+                done_tx.send(()).unwrap();
+            });
+        }
+
+        for _ in 0..workers {
+            done_rx
+                .recv_timeout(Duration::from_secs(10))
+                .expect("a thread stayed parked although slots were released");
+        }
+        assert_eq!(semaphore.current_count(), 0);
+    }
+
+    #[test]
+    #[cfg(not(feature = "parking_lot"))]
+    fn test_usable_after_poisoning() {
+        let semaphore = Arc::new(NaiveSemaphore::new(1));
+
+        let sem = Arc::clone(&semaphore);
+        let result = std::thread::spawn(move || {
+            let _locked = sem.parking.lock.lock();
+            panic!("worker failed inside the critical section");
+        })
+        .join();
+        assert!(result.is_err());
+        assert!(semaphore.parking.lock.is_poisoned());
+
+        let permit = semaphore.wait();
+        assert!(semaphore.try_wait().is_none());
+        assert!(semaphore.wait_timeout(Duration::from_millis(5)).is_err());
+        drop(permit);
+        assert_eq!(semaphore.current_count(), 0);
+        assert!(semaphore.try_wait().is_some());
+    }
+
+    #[test]
+    fn test_acquire_owned() {
+        let semaphore = Arc::new(NaiveSemaphore::new(1));
+
+        let permit = semaphore.acquire_owned();
+        assert_eq!(semaphore.current_count(), 1);
+
+        let worker = std::thread::spawn(move || {
+            let _permit = permit;
+            std::thread::sleep(Duration::from_millis(5));
+        });
+        worker.join().unwrap();
+
+        assert_eq!(semaphore.current_count(), 0);
+        assert!(semaphore.try_wait().is_some());
+    }
+
+    #[test]
+    fn test_owned_permit_handoff() {
+        fn assert_send<T: Send>(_: &T) {}
+
+        // a second release would trip the debug assertion of the strict semaphore
+        let semaphore = Arc::new(NaiveSemaphore::strict(2));
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let (release, released) = std::sync::mpsc::channel();
+        let permit = semaphore.acquire_owned();
+        assert_send(&permit);
+
+        let receiver = std::thread::spawn(move || {
+            let permit: OwnedSemaphorePermit = receiver.recv().unwrap();
+            released.recv().unwrap();
+            drop(permit);
+        });
+        std::thread::spawn(move || sender.send(permit).unwrap())
+            .join()
+            .unwrap();
+        assert_eq!(semaphore.current_count(), 1);
+
+        release.send(()).unwrap();
+        receiver.join().unwrap();
+        assert_eq!(semaphore.current_count(), 0);
+        assert_eq!(semaphore.try_release_one(), Err(OverRelease));
+    }
+
+    #[test]
+    fn test_acquire_many_owned() {
+        let semaphore = Arc::new(NaiveSemaphore::new(3));
+        assert_eq!(semaphore.acquire_many_owned(4).err(), Some(ExceedsCapacity));
+
+        let permit = semaphore.acquire_many_owned(2).unwrap();
+        assert_eq!(permit.permits(), 2);
+        assert_eq!(semaphore.current_count(), 2);
+
+        let worker = std::thread::spawn(move || {
+            let _permit = permit;
+            std::thread::sleep(Duration::from_millis(5));
+        });
+        worker.join().unwrap();
+        assert_eq!(semaphore.current_count(), 0);
+    }
+
+    #[test]
+    fn test_try_acquire_owned() {
+        let semaphore = Arc::new(NaiveSemaphore::new(2));
+
+        let first = semaphore.try_acquire_owned().unwrap();
+        let second = semaphore.try_acquire_owned().unwrap();
+        assert!(semaphore.try_acquire_owned().is_none());
+        assert_eq!(semaphore.current_count(), 2);
+
+        std::thread::spawn(move || drop(first)).join().unwrap();
+        assert!(semaphore.try_acquire_owned().is_some());
+        drop(second);
+        assert_eq!(semaphore.current_count(), 0);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "dropped with 1 outstanding permit(s)")]
+    fn test_drop_with_outstanding_permits() {
+        let semaphore = NaiveSemaphore::new(2);
+        semaphore.wait_no_guard();
+    }
+
+    #[test]
+    fn test_from_usize_in_static() {
+        // parking is shared with cancel tokens through an `Arc`, so there is no const constructor
+        static SEMAPHORE: std::sync::OnceLock<NaiveSemaphore> = std::sync::OnceLock::new();
+        let semaphore = || SEMAPHORE.get_or_init(|| NaiveSemaphore::from(2));
+
+        let handles: Vec<_> = (0..4)
+            .map(|_| std::thread::spawn(move || drop(semaphore().wait())))
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(semaphore().max_permits(), 2);
+        assert_eq!(semaphore().current_count(), 0);
+    }
+
+    #[test]
+    fn test_default_capacity() {
+        let semaphore = NaiveSemaphore::default();
+        assert!(semaphore.available_permits() >= 1);
+        assert!(semaphore.try_wait().is_some());
+    }
+
+    #[test]
+    fn test_with_permit() {
+        let semaphore = NaiveSemaphore::new(1);
+
+        let count_inside = semaphore.with_permit(|| semaphore.current_count());
+        assert_eq!(count_inside, 1);
+        assert_eq!(semaphore.current_count(), 0);
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            semaphore.with_permit(|| panic!("job failed"))
+        }));
+        assert!(result.is_err());
+        assert_eq!(semaphore.current_count(), 0);
+    }
+
+    #[test]
+    fn test_wait_indexed() {
+        let max = 3;
+        let semaphore = Arc::new(NaiveSemaphore::new(max));
+        let occupied: Arc<Vec<AtomicUsize>> =
+            Arc::new((0..max).map(|_| AtomicUsize::new(0)).collect());
+
+        let handles: Vec<_> = (0..16)
+            .map(|_| {
+                let semaphore = Arc::clone(&semaphore);
+                let occupied = Arc::clone(&occupied);
+                std::thread::spawn(move || {
+                    for _ in 0..20 {
+                        let permit = semaphore.wait_indexed();
+                        assert!(permit.slot() < max);
+                        let holders = occupied[permit.slot()].fetch_add(1, Ordering::SeqCst);
+                        assert_eq!(holders, 0, "slot {} handed out twice", permit.slot());
+                        std::thread::yield_now();
+                        occupied[permit.slot()].fetch_sub(1, Ordering::SeqCst);
+                    }
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(semaphore.current_count(), 0);
+    }
+
+    #[test]
+    fn test_wait_prefer() {
+        let semaphore = NaiveSemaphore::new(3);
+        let first = semaphore.wait_indexed();
+        assert_eq!(first.slot(), 0);
+
+        // a released slot is granted again when preferred
+        let preferred = semaphore.wait_prefer(2);
+        assert_eq!(preferred.slot(), 2);
+        drop(preferred);
+        assert_eq!(semaphore.wait_prefer(2).slot(), 2);
+
+        // a taken or out of range slot falls back to any free one
+        assert_eq!(semaphore.wait_prefer(0).slot(), 1);
+        assert_eq!(semaphore.wait_prefer(7).slot(), 1);
+
+        let _second = semaphore.wait_prefer(2);
+        assert_eq!(semaphore.wait_indexed().slot(), 1);
+        assert_eq!(semaphore.current_count(), 2);
+    }
+
+    #[test]
+    fn test_set_max_grow_wakes_waiters() {
+        let semaphore = Arc::new(NaiveSemaphore::new(1));
+        let held = semaphore.wait();
+
+        let parked: Vec<_> = (0..2)
+            .map(|_| {
+                let sem = Arc::clone(&semaphore);
+                std::thread::spawn(move || sem.wait_no_guard())
+            })
+            .collect();
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(parked.iter().all(|handle| !handle.is_finished()));
+
+        semaphore.set_max(3);
+        for handle in parked {
+            handle.join().unwrap();
+        }
+        assert_eq!(semaphore.current_count(), 3);
+        assert!(semaphore.try_wait().is_none());
+
+        drop(held);
+        semaphore.release_n(2);
+    }
+
+    #[test]
+    fn test_set_max_shrink_blocks_new_admissions() {
+        let semaphore = NaiveSemaphore::new(3);
+        let first = semaphore.wait();
+        let second = semaphore.wait();
+
+        semaphore.set_max(1);
+        assert_eq!(semaphore.current_count(), 2);
+        assert_eq!(semaphore.available_permits(), 0);
+        assert!(semaphore.try_wait().is_none());
+
+        drop(first);
+        assert!(semaphore.try_wait().is_none());
+        drop(second);
+        assert!(semaphore.try_wait().is_some());
+    }
+
+    #[test]
+    fn test_add_permits() {
+        let semaphore = Arc::new(NaiveSemaphore::new(1));
+        let held = semaphore.wait();
+        let proceeded = Arc::new(AtomicUsize::new(0));
+
+        let parked: Vec<_> = (0..3)
+            .map(|_| {
+                let sem = Arc::clone(&semaphore);
+                let proceeded = Arc::clone(&proceeded);
+                std::thread::spawn(move || {
+                    sem.wait_no_guard();
+                    proceeded.fetch_add(1, Ordering::SeqCst);
+                })
+            })
+            .collect();
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(proceeded.load(Ordering::SeqCst), 0);
+
+        semaphore.add_permits(2);
+        std::thread::sleep(Duration::from_millis(50));
+        assert_eq!(proceeded.load(Ordering::SeqCst), 2);
+        assert_eq!(semaphore.available_permits(), 0);
+
+        drop(held);
+        for handle in parked {
+            handle.join().unwrap();
+        }
+        assert_eq!(proceeded.load(Ordering::SeqCst), 3);
+        semaphore.release_n(3);
+    }
+
+    #[test]
+    fn test_split_and_merge() {
+        let semaphore = NaiveSemaphore::strict(3);
+        let mut children = semaphore.wait_n(3).split();
+        assert_eq!(children.len(), 3);
+        assert!(children.iter().all(|child| child.permits() == 1));
+        assert_eq!(semaphore.current_count(), 3);
+
+        // a double release would trip the debug assertion of the strict semaphore
+        drop(children.pop());
+        assert_eq!(semaphore.current_count(), 2);
+
+        let mut merged = children.pop().unwrap();
+        merged.merge(children.pop().unwrap());
+        assert_eq!(merged.permits(), 2);
+        assert_eq!(semaphore.current_count(), 2);
+        drop(merged);
+        assert_eq!(semaphore.current_count(), 0);
+    }
+
+    #[test]
+    fn test_transfer_to() {
+        let (origin, target) = (NaiveSemaphore::strict(2), NaiveSemaphore::strict(2));
+        let full = target.wait_n(2);
+
+        std::thread::scope(|s| {
+            let mover = s.spawn(|| {
+                let mut permit = origin.wait();
+                permit.mark_active();
+                permit.transfer_to(&target)
+            });
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while target.waiting_count() == 0 {
+                assert!(Instant::now() < deadline, "the transfer never blocked");
+                std::thread::sleep(Duration::from_millis(1));
+            }
+            // still held by the origin while the target is full
+            assert_eq!(origin.current_count(), 1);
+
+            drop(full);
+            let moved = mover.join().unwrap();
+            assert_eq!(moved.permits(), 1);
+            assert_eq!((origin.current_count(), target.current_count()), (0, 1));
+            assert_eq!((origin.active_count(), target.active_count()), (0, 1));
+
+            // moving back and forth does not leak
+            let back = moved.transfer_to(&origin);
+            assert_eq!((origin.current_count(), target.current_count()), (1, 0));
+            drop(back);
+        });
+        assert_eq!(origin.current_count(), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot merge permits of different semaphores")]
+    fn test_merge_rejects_other_semaphore() {
+        let (first, second) = (NaiveSemaphore::new(1), NaiveSemaphore::new(1));
+        let mut permit = first.wait();
+        permit.merge(second.wait());
+    }
+
+    #[test]
+    fn test_forget() {
+        let semaphore = NaiveSemaphore::new(3);
+        semaphore.wait().forget();
+        assert_eq!(semaphore.available_permits(), 2);
+
+        // the forgotten permit stays taken across other acquisitions and releases
+        drop(semaphore.wait_n(2));
+        assert_eq!(semaphore.available_permits(), 2);
+        assert!(semaphore.wait_timeout(Duration::ZERO).is_ok());
+        let _held = semaphore.wait_n(2);
+        assert!(semaphore.try_wait().is_none());
+    }
+
+    #[test]
+    #[cfg(feature = "tracing")]
+    #[tracing_test::traced_test]
+    fn test_tracing_events() {
+        // only events of the test thread are captured, so the test thread does the waiting
+        let semaphore = Arc::new(NaiveSemaphore::new(1));
+        let held = semaphore.acquire_owned();
+        let holder = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            drop(held);
+        });
+
+        drop(semaphore.wait());
+        holder.join().unwrap();
+
+        assert!(logs_contain("semaphore_wait"));
+        assert!(logs_contain("acquired"));
+        assert!(logs_contain("released"));
+        assert!(logs_contain("current_count=1"));
+    }
+
+    /// Captures the messages of every thread, as the logger is global to the test binary.
+    #[cfg(feature = "log")]
+    struct CapturingLogger {
+        messages: std::sync::Mutex<Vec<(std::thread::ThreadId, String)>>,
+    }
+
+    #[cfg(feature = "log")]
+    impl log::Log for CapturingLogger {
+        fn enabled(&self, _metadata: &log::Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &log::Record) {
+            if record.target().starts_with("naive_semaphore") {
+                let message = (std::thread::current().id(), record.args().to_string());
+                self.messages.lock().unwrap().push(message);
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    #[test]
+    #[cfg(feature = "log")]
+    fn test_log_messages() {
+        static LOGGER: CapturingLogger = CapturingLogger {
+            messages: std::sync::Mutex::new(Vec::new()),
+        };
+        log::set_logger(&LOGGER).unwrap();
+        log::set_max_level(log::LevelFilter::Debug);
+
+        let semaphore = NaiveSemaphore::new(3);
+        drop(semaphore.wait_n(2));
+
+        // only the messages of the test thread, the others run concurrently
+        let test_thread = std::thread::current().id();
+        let messages: Vec<String> = LOGGER
+            .messages
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(thread, _)| *thread == test_thread)
+            .map(|(_, message)| message.clone())
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                "acquired 2 permit(s), current count 2",
+                "released 2 permit(s), current count 0"
+            ]
+        );
+    }
+
+    #[derive(Default)]
+    struct CountingObserver {
+        wait_starts: AtomicUsize,
+        acquired: AtomicUsize,
+        released: AtomicUsize,
+        timeouts: AtomicUsize,
+    }
+
+    impl SemaphoreObserver for CountingObserver {
+        fn on_wait_start(&self) {
+            self.wait_starts.fetch_add(1, Ordering::SeqCst);
+        }
+
+        fn on_acquired(&self, permits: usize) {
+            self.acquired.fetch_add(permits, Ordering::SeqCst);
+        }
+
+        fn on_released(&self, permits: usize) {
+            self.released.fetch_add(permits, Ordering::SeqCst);
+        }
+
+        fn on_timeout(&self) {
+            self.timeouts.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn test_observer_hooks() {
+        let observer = Arc::new(CountingObserver::default());
+        let semaphore = Arc::new(NaiveSemaphore::with_observer(1, observer.clone()));
+
+        let held = semaphore.acquire_owned();
+        assert!(semaphore.wait_timeout(Duration::from_millis(5)).is_err());
+
+        let holder = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            drop(held);
+        });
+        drop(semaphore.wait());
+        holder.join().unwrap();
+
+        assert_eq!(observer.wait_starts.load(Ordering::SeqCst), 2);
+        assert_eq!(observer.acquired.load(Ordering::SeqCst), 2);
+        assert_eq!(observer.released.load(Ordering::SeqCst), 2);
+        assert_eq!(observer.timeouts.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_builder() {
+        let observer = Arc::new(CountingObserver::default());
+        let (sender, events) = std::sync::mpsc::sync_channel(16);
+        let deadlocks = Arc::new(AtomicUsize::new(0));
+        let reported = Arc::clone(&deadlocks);
+        let semaphore = Arc::new(
+            NaiveSemaphore::builder()
+                .max(1)
+                .strict()
+                .reentrancy_check()
+                .spin(4)
+                .max_waiters(1)
+                .observer(observer.clone())
+                .event_sink(sender)
+                .stats()
+                .wait_histogram()
+                .deadlock_timeout(Duration::from_millis(10))
+                .on_deadlock(move || {
+                    reported.fetch_add(1, Ordering::SeqCst);
+                })
+                .build()
+                .unwrap(),
+        );
+
+        let own = semaphore.wait();
+        assert!(matches!(
+            semaphore.wait_or_would_deadlock(),
+            Err(AcquireError::WouldDeadlock)
+        ));
+        drop(own);
+
+        // the only waiter allowed blocks until the deadlock handler frees the permit
+        let held = semaphore.acquire_owned();
+        let sem = Arc::clone(&semaphore);
+        let waiter = std::thread::spawn(move || drop(sem.wait_or_overloaded().unwrap()));
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while semaphore.waiting_count() == 0 {
+            assert!(Instant::now() < deadline, "the waiter never blocked");
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        assert!(matches!(
+            semaphore.wait_or_overloaded(),
+            Err(AcquireError::Overloaded)
+        ));
+        while deadlocks.load(Ordering::SeqCst) == 0 {
+            assert!(Instant::now() < deadline, "the deadlock was never reported");
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        drop(held);
+        waiter.join().unwrap();
+
+        assert!(semaphore.try_release_one().is_err());
+        assert_eq!(observer.acquired.load(Ordering::SeqCst), 3);
+        assert_eq!(semaphore.stats().unwrap().total_acquisitions, 3);
+        assert!(semaphore.wait_latency_percentile(100.0).unwrap() > Duration::ZERO);
+        assert_eq!(events.try_iter().count(), 7);
+        assert_eq!(semaphore.current_count(), 0);
+    }
+
+    #[test]
+    fn test_release_one_reports_wakeups() {
+        let semaphore = Arc::new(NaiveSemaphore::new(1));
+        semaphore.wait_no_guard();
+        assert!(!semaphore.release_one());
+
+        semaphore.wait_no_guard();
+        let sem = Arc::clone(&semaphore);
+        let waiter = std::thread::spawn(move || drop(sem.wait()));
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while semaphore.lock().threads == 0 {
+            assert!(Instant::now() < deadline, "the waiter never parked");
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        assert!(semaphore.release_one());
+        waiter.join().unwrap();
+
+        // the permit already freed wakes nobody
+        assert!(!semaphore.release_one());
+        assert_eq!(semaphore.current_count(), 0);
+    }
+
+    #[test]
+    fn test_reentrant_wait_would_deadlock() {
+        let semaphore = NaiveSemaphore::with_reentrancy_check(2);
+        let first = semaphore.wait();
+        // a free permit is fine, also for a thread holding one already
+        let last = semaphore.wait_or_would_deadlock().unwrap();
+        assert!(matches!(
+            semaphore.wait_or_would_deadlock(),
+            Err(AcquireError::WouldDeadlock)
+        ));
+
+        // another thread is not holding any and waits as usual
+        std::thread::scope(|s| {
+            let waiter = s.spawn(|| drop(semaphore.wait_or_would_deadlock().unwrap()));
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while semaphore.waiting_count() == 0 {
+                assert!(Instant::now() < deadline, "the waiter never blocked");
+                std::thread::sleep(Duration::from_millis(1));
+            }
+            drop(last);
+            waiter.join().unwrap();
+        });
+
+        let parts = semaphore.wait().split();
+        drop(first);
+        assert_eq!(semaphore.holders.as_ref().unwrap().lock().len(), 1);
+        drop(parts);
+        assert!(semaphore.holders.as_ref().unwrap().lock().is_empty());
+    }
+
+    #[test]
+    fn test_non_blocking_never_parks() {
+        let semaphore = NaiveSemaphore::non_blocking(1);
+        // blocking calls are fine as long as they need not park
+        let held = semaphore.wait();
+        assert!(semaphore.try_wait().is_none());
+
+        let parked = |wait: &dyn Fn()| {
+            let panic = std::panic::catch_unwind(std::panic::AssertUnwindSafe(wait)).unwrap_err();
+            assert_eq!(
+                panic.downcast_ref::<&str>(),
+                Some(&"a non-blocking semaphore never parks a thread, use `try_wait` instead")
+            );
+        };
+        parked(&|| drop(semaphore.wait()));
+        parked(&|| drop(semaphore.wait_timeout(Duration::from_secs(5))));
+        parked(&|| drop(semaphore.wait_with_priority(1)));
+        parked(&|| drop(semaphore.wait_or_closed()));
+        parked(&|| drop(semaphore.wait_with(|| ())));
+        parked(&|| drop(semaphore.wait_queued().wait()));
+        let token = CancelToken::new();
+        parked(&|| drop(semaphore.wait_cancellable(&token)));
+
+        // nothing is left behind by the refused waits
+        assert_eq!(token.registrations(), 0);
+        assert_eq!(semaphore.waiting_count(), 0);
+        assert!(semaphore.lock().by_priority.is_empty());
+        drop(held);
+        drop(semaphore.wait_with_priority(1));
+        assert_eq!(semaphore.current_count(), 0);
+    }
+
+    #[test]
+    fn test_builder_requires_max() {
+        assert_eq!(
+            NaiveSemaphore::builder().build().unwrap_err(),
+            InvalidConfig
+        );
+        assert_eq!(
+            NaiveSemaphore::builder()
+                .max(0)
+                .strict()
+                .build()
+                .unwrap_err(),
+            InvalidConfig
+        );
+    }
+
+    #[test]
+    fn test_builder_rejects_contradicting_options() {
+        assert!(matches!(
+            NaiveSemaphore::builder().max(1).on_deadlock(|| ()).build(),
+            Err(InvalidConfig)
+        ));
+        assert!(matches!(
+            NaiveSemaphore::builder()
+                .max(1)
+                .non_blocking()
+                .deadlock_timeout(Duration::from_secs(1))
+                .build(),
+            Err(InvalidConfig)
+        ));
+        assert!(NaiveSemaphore::builder()
+            .max(1)
+            .deadlock_timeout(Duration::from_secs(1))
+            .on_deadlock(|| ())
+            .build()
+            .is_ok());
+    }
+
+    #[test]
+    fn test_builder_fair() {
+        let semaphore = NaiveSemaphore::builder().max(1).fair().build().unwrap();
+        let held = semaphore.wait();
+        assert!(semaphore.try_wait().is_none());
+        drop(held);
+
+        let handoff = NaiveSemaphore::builder()
+            .max(2)
+            .fair()
+            .handoff()
+            .build()
+            .unwrap();
+        assert_eq!(handoff.available_permits(), 2);
+        drop(handoff.wait());
+
+        assert!(matches!(
+            NaiveSemaphore::builder().fair().build(),
+            Err(InvalidConfig)
+        ));
+        assert!(matches!(
+            NaiveSemaphore::builder().max(1).spin(4).fair().build(),
+            Err(InvalidConfig)
+        ));
+    }
+
+    #[test]
+    fn test_event_sink() {
+        let (sender, events) = std::sync::mpsc::sync_channel(16);
+        let semaphore = NaiveSemaphore::with_event_sink(1, sender);
+
+        let start = Instant::now();
+        let held = semaphore.wait();
+        assert!(semaphore.wait_timeout(Duration::from_millis(5)).is_err());
+        drop(held);
+
+        let events: Vec<_> = events.try_iter().collect();
+        assert!(matches!(
+            events[..],
+            [
+                SemaphoreEvent::Acquired {
+                    current_count: 1,
+                    permits: 1,
+                    ..
+                },
+                SemaphoreEvent::WaitStarted {
+                    current_count: 1,
+                    ..
+                },
+                SemaphoreEvent::TimedOut {
+                    current_count: 1,
+                    ..
+                },
+                SemaphoreEvent::Released {
+                    current_count: 0,
+                    permits: 1,
+                    ..
+                },
+            ]
+        ));
+        assert!(events.windows(2).all(|pair| pair[0].at() <= pair[1].at()));
+        assert!(events[0].at() >= start);
+    }
+
+    #[test]
+    fn test_event_sink_never_blocks() {
+        let (sender, events) = std::sync::mpsc::sync_channel(1);
+        let semaphore = NaiveSemaphore::with_event_sink(1, sender);
+
+        // nobody drains the channel while it is full
+        for _ in 0..10 {
+            drop(semaphore.wait());
+        }
+        assert_eq!(events.try_iter().count(), 1);
+    }
+
+    #[test]
+    fn test_stats() {
+        assert_eq!(NaiveSemaphore::new(1).stats(), None);
+
+        let semaphore = Arc::new(NaiveSemaphore::with_stats(2));
+        let wait_calls = 12;
+
+        let handles: Vec<_> = (0..wait_calls)
+            .map(|_| {
+                let semaphore = Arc::clone(&semaphore);
+                std::thread::spawn(move || {
+                    let _permit = semaphore.wait();
+                    std::thread::sleep(Duration::from_millis(5));
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let stats = semaphore.stats().unwrap();
+        assert_eq!(stats.total_acquisitions, wait_calls);
+        assert!(stats.blocked_count > 0);
+        assert!(stats.total_blocked > Duration::ZERO);
+    }
+
+    #[test]
+    fn test_wait_latency_percentile() {
+        assert_eq!(NaiveSemaphore::new(1).wait_latency_percentile(50.0), None);
+
+        let semaphore = Arc::new(NaiveSemaphore::with_wait_histogram(1));
+        // most acquisitions find the permit free
+        for _ in 0..95 {
+            drop(semaphore.wait());
+        }
+        // a few block behind a permit held for a while
+        for _ in 0..5 {
+            let permit = semaphore.wait();
+            let waiting = {
+                let semaphore = Arc::clone(&semaphore);
+                std::thread::spawn(move || drop(semaphore.wait()))
+            };
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while semaphore.waiting_count() == 0 {
+                assert!(Instant::now() < deadline, "the thread never blocked");
+                std::thread::sleep(Duration::from_millis(1));
+            }
+            std::thread::sleep(Duration::from_millis(10));
+            drop(permit);
+            waiting.join().unwrap();
+        }
+
+        let p50 = semaphore.wait_latency_percentile(50.0).unwrap();
+        let p99 = semaphore.wait_latency_percentile(99.0).unwrap();
+        assert!(p99 > p50, "p99 {:?} does not exceed p50 {:?}", p99, p50);
+        assert!(p99 >= Duration::from_millis(10));
+    }
+
+    #[test]
+    #[should_panic(expected = "the percentile 101 is not within 0.0..=100.0")]
+    fn test_wait_latency_percentile_rejects_percentile() {
+        NaiveSemaphore::with_wait_histogram(1).wait_latency_percentile(101.0);
+    }
+
+    #[test]
+    fn test_permit_timing() {
+        let semaphore = Arc::new(NaiveSemaphore::new(1));
+        let permit = semaphore.wait();
+        assert_eq!(permit.wait_duration(), Duration::ZERO);
+
+        let waiting = {
+            let semaphore = Arc::clone(&semaphore);
+            std::thread::spawn(move || semaphore.wait().wait_duration())
+        };
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(permit.held_duration() >= Duration::from_millis(20));
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while semaphore.waiting_count() == 0 {
+            assert!(Instant::now() < deadline, "the thread never blocked");
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        drop(permit);
+        assert!(waiting.join().unwrap() > Duration::ZERO);
+    }
+
+    #[test]
+    fn test_with_spin() {
+        let max = 2;
+        let semaphore = Arc::new(NaiveSemaphore::with_spin(max, 100));
+        let running = Arc::new(AtomicUsize::new(0));
+
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let semaphore = Arc::clone(&semaphore);
+                let running = Arc::clone(&running);
+                std::thread::spawn(move || {
+                    for _ in 0..200 {
+                        let _permit = semaphore.wait();
+                        assert!(running.fetch_add(1, Ordering::SeqCst) < max);
+                        running.fetch_sub(1, Ordering::SeqCst);
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(semaphore.current_count(), 0);
+
+        // a waiter giving up spinning still parks and gets woken up
+        let held = semaphore.wait_n(max);
+        let waiter = {
+            let semaphore = Arc::clone(&semaphore);
+            std::thread::spawn(move || drop(semaphore.wait()))
+        };
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(!waiter.is_finished());
+        drop(held);
+        waiter.join().unwrap();
+    }
+
+    #[test]
+    fn test_try_wait() {
+        let semaphore = NaiveSemaphore::new(2);
+
+        let first = semaphore.try_wait();
+        let second = semaphore.try_wait();
+        assert!(first.is_some());
+        assert!(second.is_some());
+        assert!(semaphore.try_wait().is_none());
+        assert_eq!(semaphore.current_count(), 2);
+
+        drop(first);
+        assert!(semaphore.try_wait().is_some());
+    }
+
+    #[test]
+    fn test_try_acquire_n() {
+        let semaphore = NaiveSemaphore::new(5);
+        let taken = semaphore.wait_n(3);
+
+        assert!(semaphore.try_acquire_n(3).is_none());
+        assert_eq!(semaphore.current_count(), 3);
+        let batch = semaphore.try_acquire_n(2).unwrap();
+        assert_eq!(semaphore.current_count(), 5);
+        assert!(semaphore.try_acquire_n(1).is_none());
+        assert!(semaphore.try_acquire_n(6).is_none());
+
+        drop(batch);
+        drop(taken);
+        assert_eq!(semaphore.current_count(), 0);
+    }
+
+    #[test]
+    fn test_try_wait_never_overshoots() {
+        let max = 3;
+        let semaphore = Arc::new(NaiveSemaphore::new(max));
+        let acquired = Arc::new(AtomicUsize::new(0));
+        let barrier = Arc::new(std::sync::Barrier::new(16));
+
+        let handles: Vec<_> = (0..16)
+            .map(|_| {
+                let semaphore = Arc::clone(&semaphore);
+                let acquired = Arc::clone(&acquired);
+                let barrier = Arc::clone(&barrier);
+                std::thread::spawn(move || {
+                    barrier.wait();
+                    if let Some(permit) = semaphore.try_wait() {
+                        acquired.fetch_add(1, Ordering::SeqCst);
+                        std::mem::forget(permit);
+                    }
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(acquired.load(Ordering::SeqCst), max);
+        assert_eq!(semaphore.current_count(), max);
+        semaphore.release_n(max);
+    }
+
+    #[test]
+    fn test_try_wait_for() {
+        let semaphore = NaiveSemaphore::new(1);
+        let permit = semaphore.try_wait_for(Duration::from_millis(20));
+        assert!(permit.is_some());
+
+        assert!(semaphore.try_wait_for(Duration::from_millis(20)).is_none());
+        assert_eq!(semaphore.current_count(), 1);
+        drop(permit);
+        assert_eq!(semaphore.current_count(), 0);
+    }
+
+    #[test]
+    fn test_wait_until() {
+        let semaphore = NaiveSemaphore::new(1);
+        // a passed deadline still takes a free permit
+        let permit = semaphore.wait_until(Instant::now() - Duration::from_millis(1));
+        assert!(permit.is_ok());
+
+        let start = Instant::now();
+        let result = semaphore.wait_until(start + Duration::from_millis(50));
+        assert_eq!(result.err(), Some(AcquireError::TimedOut));
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(50));
+        assert!(elapsed < Duration::from_secs(1));
+        assert_eq!(semaphore.current_count(), 1);
+    }
+
+    #[test]
+    fn test_jittered_range() {
+        let base = Duration::from_millis(100);
+        let samples: Vec<_> = (0..1_000)
+            .map(|_| jittered(base, 0.1, random_unit()))
+            .collect();
+        assert!(samples
+            .iter()
+            .all(|d| (Duration::from_millis(90)..=Duration::from_millis(110)).contains(d)));
+        // spread over the range rather than stuck in one place
+        assert!(samples.iter().any(|d| *d < Duration::from_millis(95)));
+        assert!(samples.iter().any(|d| *d > Duration::from_millis(105)));
+
+        assert_eq!(jittered(base, 0.0, random_unit()), base);
+        assert_eq!(jittered(base, 0.5, 0.0), Duration::from_millis(50));
+    }
+
+    #[test]
+    fn test_wait_timeout_jittered() {
+        let semaphore = NaiveSemaphore::new(1);
+        let _held = semaphore.wait();
+        for _ in 0..5 {
+            let start = Instant::now();
+            assert!(semaphore
+                .wait_timeout_jittered(Duration::from_millis(20), 0.5)
+                .is_err());
+            let waited = start.elapsed();
+            assert!(
+                waited >= Duration::from_millis(10),
+                "waited only {:?}",
+                waited
+            );
+            assert!(waited < Duration::from_secs(1), "waited {:?}", waited);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "the jitter fraction 1.5 is not within 0.0..=1.0")]
+    fn test_wait_timeout_jittered_rejects_fraction() {
+        let _ = NaiveSemaphore::new(1).wait_timeout_jittered(Duration::ZERO, 1.5);
+    }
+
+    #[test]
+    fn test_wait_timeout() {
+        let max = 2;
+        let semaphore = Arc::new(NaiveSemaphore::new(max));
+        let barrier = Arc::new(std::sync::Barrier::new(max + 1));
+
+        let holders: Vec<_> = (0..max)
+            .map(|_| {
+                let semaphore = Arc::clone(&semaphore);
+                let barrier = Arc::clone(&barrier);
+                std::thread::spawn(move || {
+                    let _permit = semaphore.wait();
+                    barrier.wait();
+                    std::thread::sleep(Duration::from_millis(200));
+                })
+            })
+            .collect();
+
+        barrier.wait();
+        let sw = Stopwatch::start_new();
+        let result = semaphore.wait_timeout(Duration::from_millis(20));
+        assert_eq!(result.err(), Some(AcquireError::TimedOut));
+        assert!(sw.elapsed_ms() >= 20);
+        assert_eq!(semaphore.current_count(), max);
+
+        for holder in holders {
+            holder.join().unwrap();
+        }
+        assert!(semaphore.wait_timeout(Duration::from_millis(20)).is_ok());
+    }
+
+    #[test]
+    fn test_current_count_never_exceeds_max() {
+        let max = 2;
+        let semaphore = Arc::new(NaiveSemaphore::new(max));
+        let observed_max = Arc::new(AtomicUsize::new(0));
+
+        let handles: Vec<_> = (0..32)
+            .map(|_| {
+                let semaphore = Arc::clone(&semaphore);
+                let observed_max = Arc::clone(&observed_max);
+                std::thread::spawn(move || {
+                    for _ in 0..50 {
+                        let _permit = semaphore.wait();
+                        observed_max.fetch_max(semaphore.current_count(), Ordering::SeqCst);
+                        std::thread::yield_now();
+                    }
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert!(observed_max.load(Ordering::SeqCst) <= max);
+        assert_eq!(semaphore.current_count(), 0);
+    }
+
+    #[test]
+    fn test_current_count_read_concurrently() {
+        let max = 3;
+        let semaphore = Arc::new(NaiveSemaphore::new(max));
+        let done = Arc::new(std::sync::atomic::AtomicBool::new(false));
+
+        let readers: Vec<_> = (0..2)
+            .map(|_| {
+                let semaphore = Arc::clone(&semaphore);
+                let done = Arc::clone(&done);
+                std::thread::spawn(move || {
+                    let mut reads = 0;
+                    while !done.load(Ordering::SeqCst) {
+                        assert!(semaphore.current_count() <= max);
+                        reads += 1;
+                    }
+                    reads
+                })
+            })
+            .collect();
+        let workers: Vec<_> = (0..8)
+            .map(|idx| {
+                let semaphore = Arc::clone(&semaphore);
+                std::thread::spawn(move || {
+                    for round in 0..200 {
+                        // mix the acquisition paths, the bulk one included
+                        let permit = match (idx + round) % 3 {
+                            0 => semaphore.wait(),
+                            1 => semaphore.wait_n(2),
+                            _ => match semaphore.try_wait() {
+                                Some(permit) => permit,
+                                None => continue,
+                            },
+                        };
+                        assert!(semaphore.current_count() >= permit.permits());
+                    }
+                })
+            })
+            .collect();
+
+        for handle in workers {
+            handle.join().unwrap();
+        }
+        done.store(true, Ordering::SeqCst);
+        for handle in readers {
+            assert!(handle.join().unwrap() > 0);
+        }
+        assert_eq!(semaphore.current_count(), 0);
+    }
+
+    #[test]
+    fn test_peak_concurrency_equals_max() {
+        let max = 3;
+        let semaphore = Arc::new(NaiveSemaphore::new(max));
+        let running = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+
+        let handles: Vec<_> = (0..24)
+            .map(|_| {
+                let semaphore = Arc::clone(&semaphore);
+                let running = Arc::clone(&running);
+                let peak = Arc::clone(&peak);
+                std::thread::spawn(move || {
+                    let _permit = semaphore.wait();
+                    let now_running = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    peak.fetch_max(now_running, Ordering::SeqCst);
+                    std::thread::sleep(Duration::from_millis(5));
+                    running.fetch_sub(1, Ordering::SeqCst);
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(peak.load(Ordering::SeqCst), max);
+    }
+
+    #[test]
+    fn test_locked_derived_from_count() {
+        let semaphore = NaiveSemaphore::new(3);
+        let mut held = Vec::new();
+        // xorshift, a fixed seed keeps failures reproducible
+        let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut next = |bound: u64| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state % bound) as usize
+        };
+
+        for _ in 0..10_000 {
+            match next(7) {
+                0 | 1 => held.extend(semaphore.try_wait()),
+                2 => {
+                    let n = next(3) + 1;
+                    if semaphore.available_permits() >= n {
+                        held.push(semaphore.wait_n(n));
+                    }
+                }
+                3 => {
+                    if !held.is_empty() {
+                        drop(held.swap_remove(next(held.len() as u64)));
+                    }
+                }
+                4 => semaphore.set_max(next(6) + 1),
+                5 => semaphore.add_permits(next(2)),
+                _ => {
+                    if next(10) == 0 {
+                        // the permits still held only saturate at zero when dropped later
+                        semaphore.release_all();
+                    }
+                }
+            }
+
+            let max = semaphore.max_permits();
+            let current = semaphore.current_count();
+            assert_eq!(semaphore.is_full(), current >= max);
+            assert_eq!(semaphore.available_permits(), max.saturating_sub(current));
+            let formatted = format!("{:?}", semaphore);
+            assert!(formatted.contains(&format!("locked: {}", current >= max)));
+        }
+        drop(held);
+        semaphore.release_all();
+    }
+
+    #[derive(Debug, Clone, Copy)]
+    enum Op {
+        Wait,
+        TryWait,
+        Release,
+    }
+
+    fn op() -> impl Strategy<Value = Op> {
+        prop_oneof![Just(Op::Wait), Just(Op::TryWait), Just(Op::Release)]
+    }
+
+    /// Apply 'ops' and check the invariants visible to a single thread after each one,
+    /// returning the permits still held.
+    fn apply_ops<'a>(
+        semaphore: &'a NaiveSemaphore,
+        ops: &[Op],
+        acquired: &AtomicUsize,
+        released: &AtomicUsize,
+    ) -> Vec<SemaphorePermit<'a>> {
+        let max = semaphore.max_permits();
+        let mut held = Vec::new();
+        for op in ops {
+            match op {
+                // bounded, the other threads keep their permits until they are joined
+                Op::Wait => match semaphore.wait_timeout(Duration::from_millis(1)) {
+                    Ok(permit) => held.push(permit),
+                    Err(error) => {
+                        assert_eq!(error, AcquireError::TimedOut);
+                        continue;
+                    }
+                },
+                Op::TryWait => match semaphore.try_wait() {
+                    Some(permit) => held.push(permit),
+                    None => continue,
+                },
+                Op::Release => match held.pop() {
+                    Some(permit) => {
+                        drop(permit);
+                        released.fetch_add(1, Ordering::SeqCst);
+                        continue;
+                    }
+                    None => continue,
+                },
+            }
+            acquired.fetch_add(1, Ordering::SeqCst);
+            let current = semaphore.current_count();
+            assert!(current <= max, "{} permits taken of {}", current, max);
+            assert!(current >= held.len(), "own permits missing from the count");
+        }
+        held
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn prop_single_thread_count_exact(max in 1usize..4, ops in vec(op(), 0..64)) {
+            let semaphore = NaiveSemaphore::new(max);
+            let (acquired, released) = (AtomicUsize::new(0), AtomicUsize::new(0));
+            let held = apply_ops(&semaphore, &ops, &acquired, &released);
+
+            prop_assert_eq!(semaphore.current_count(), held.len());
+            prop_assert_eq!(semaphore.is_full(), held.len() == max);
+            drop(held);
+            prop_assert_eq!(semaphore.current_count(), 0);
+        }
+
+        #[test]
+        fn prop_threads_balance_acquisitions_and_releases(
+            max in 1usize..4,
+            threads in vec(vec(op(), 0..32), 1..4),
+        ) {
+            let semaphore = NaiveSemaphore::new(max);
+            let (acquired, released) = (AtomicUsize::new(0), AtomicUsize::new(0));
+            let held: Vec<_> = std::thread::scope(|s| {
+                let handles: Vec<_> = threads
+                    .iter()
+                    .map(|ops| s.spawn(|| apply_ops(&semaphore, ops, &acquired, &released)))
+                    .collect();
+                handles
+                    .into_iter()
+                    .flat_map(|handle| handle.join().unwrap())
+                    .collect()
+            });
+
+            let outstanding = acquired.load(Ordering::SeqCst) - released.load(Ordering::SeqCst);
+            prop_assert_eq!(semaphore.current_count(), outstanding);
+            prop_assert_eq!(held.len(), outstanding);
+            prop_assert_eq!(semaphore.is_full(), outstanding >= max);
+            drop(held);
+            prop_assert_eq!(semaphore.current_count(), 0);
+        }
+    }
+
+    #[test]
+    fn test_config_round_trip() {
+        let semaphore = NaiveSemaphore::new(3);
+        let permit = semaphore.wait();
+        let config = semaphore.to_config();
+        assert_eq!((config.max(), config.current()), (3, 1));
+
+        let restored = NaiveSemaphore::from_config(config);
+        assert_eq!(restored.max_permits(), 3);
+        assert_eq!(restored.current_count(), 1);
+        restored.release_one();
+        drop(permit);
+    }
+
+    #[test]
+    fn test_wait_with_priority() {
+        let semaphore = Arc::new(NaiveSemaphore::new(1));
+        let admitted = Arc::new(Mutex::new(Vec::new()));
+        let held = semaphore.wait();
+
+        let handles: Vec<_> = [1, 9]
+            .into_iter()
+            .map(|priority| {
+                let semaphore = Arc::clone(&semaphore);
+                let admitted = Arc::clone(&admitted);
+                let handle = std::thread::spawn(move || {
+                    let _permit = semaphore.wait_with_priority(priority);
+                    admitted.lock().unwrap().push(priority);
+                });
+                // park the low priority first, it still has to let the high one pass
+                std::thread::sleep(Duration::from_millis(20));
+                handle
+            })
+            .collect();
 
-impl NaiveSemaphore {
-    pub fn new(max: usize) -> Self {
-        Self {
-            max,
-            current: Counter::new(0),
-            is_locked: Mutex::new(false),
-            waiter: Condvar::new(),
+        drop(held);
+        for handle in handles {
+            handle.join().unwrap();
         }
+        assert_eq!(*admitted.lock().unwrap(), [9, 1]);
     }
 
-    /// The count of currently running threads.
-    pub fn current_count(&self) -> usize {
-        self.current.get()
+    #[test]
+    fn test_priority_aging() {
+        let mut parked = Parked::default();
+        let low = parked.enqueue(1);
+        for _ in 0..2 {
+            let high = parked.enqueue(3);
+            assert_eq!(parked.head(), Some(high));
+            parked.dequeue(high);
+        }
+        // passed over twice, the long waiting low priority ties with newcomers and goes first
+        let high = parked.enqueue(3);
+        assert_eq!(parked.head(), Some(low));
+        parked.dequeue(low);
+        assert_eq!(parked.head(), Some(high));
     }
 
-    /// Release a waiting thread, reduce the current count.
-    pub fn release_one(&self) {
-        let current = self.current.get();
+    /// The longest a thread may go without a permit in the starvation tests, generous enough
+    /// for loaded CI machines. Lower it to tighten the fairness the tests enforce.
+    const STARVATION_BOUND: Duration = Duration::from_secs(2);
 
-        if current >= 1 {
-            let mut is_locked = self.is_locked.lock().unwrap();
+    /// Let 'threads' threads, tagged with their index, acquire with 'acquire' and hold the
+    /// permit briefly for 'rounds' rounds each. Returns the longest time each of them waited
+    /// between two acquisitions, i.e. from its start or its last release to its next permit.
+    fn worst_acquisition_gaps<G>(
+        threads: usize,
+        rounds: usize,
+        acquire: impl Fn() -> G + Sync,
+    ) -> Vec<Duration> {
+        std::thread::scope(|s| {
+            let handles: Vec<_> = (0..threads)
+                .map(|_| {
+                    s.spawn(|| {
+                        let mut worst = Duration::ZERO;
+                        for _ in 0..rounds {
+                            let since = Instant::now();
+                            let permit = acquire();
+                            worst = worst.max(since.elapsed());
+                            std::thread::sleep(Duration::from_micros(200));
+                            drop(permit);
+                        }
+                        worst
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap())
+                .collect()
+        })
+    }
 
-            if *is_locked {
-                *is_locked = false;
-                self.waiter.notify_one(); // wake up one waiting thread
-            }
+    fn assert_no_starvation(gaps: &[Duration]) {
+        println!("worst acquisition gap: {:?}", gaps.iter().max());
+        for (id, gap) in gaps.iter().enumerate() {
+            assert!(
+                *gap <= STARVATION_BOUND,
+                "thread {} starved for {:?}, longer than {:?}",
+                id,
+                gap,
+                STARVATION_BOUND
+            );
+        }
+    }
+
+    #[test]
+    fn test_starvation_bound() {
+        let semaphore = NaiveSemaphore::new(2);
+        let gaps = worst_acquisition_gaps(16, 50, || semaphore.wait());
+        assert_no_starvation(&gaps);
+        assert_eq!(semaphore.current_count(), 0);
+    }
+
+    #[test]
+    fn test_starvation_bound_fair() {
+        let semaphore = crate::FairSemaphore::new(2);
+        let gaps = worst_acquisition_gaps(16, 50, || semaphore.wait());
+        assert_no_starvation(&gaps);
+        assert_eq!(semaphore.current_count(), 0);
+    }
+
+    #[test]
+    fn test_merge() {
+        let a = NaiveSemaphore::strict(2);
+        let b = NaiveSemaphore::new(3);
+        a.wait_no_guard();
+        b.wait_no_guard();
+        b.wait_no_guard();
+        b.wait_n(1).forget();
+
+        let merged = a.merge(b);
+        assert_eq!(merged.max_permits(), 5);
+        assert_eq!(merged.current_count(), 4);
+        assert_eq!(merged.available_permits(), 1);
+        let permit = merged.try_wait();
+        assert!(permit.is_some());
+        assert!(merged.try_wait().is_none());
+
+        drop(permit);
+        merged.release_n(3);
+        // the forgotten permit of 'b' stays taken
+        assert_eq!(merged.current_count(), 1);
+        assert_eq!(merged.try_release_n(2), Err(OverRelease));
+    }
+
+    #[test]
+    fn test_wait_or_overloaded() {
+        let semaphore = Arc::new(NaiveSemaphore::with_max_waiters(1, 2));
+        let held = semaphore.wait_or_overloaded().unwrap();
+
+        let waiting: Vec<_> = (0..2)
+            .map(|_| {
+                let semaphore = Arc::clone(&semaphore);
+                std::thread::spawn(move || semaphore.wait_or_overloaded().is_ok())
+            })
+            .collect();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while semaphore.waiting_count() < 2 {
+            assert!(Instant::now() < deadline, "the threads never blocked");
+            std::thread::sleep(Duration::from_millis(1));
+        }
 
-            self.current.decr();
+        // running and waiting slots are full, the next thread is shed
+        assert_eq!(
+            semaphore.wait_or_overloaded().err(),
+            Some(AcquireError::Overloaded)
+        );
+        assert_eq!(semaphore.waiting_count(), 2);
+
+        drop(held);
+        for handle in waiting {
+            assert!(handle.join().unwrap());
         }
+        assert!(semaphore.wait_or_overloaded().is_ok());
     }
 
-    /// Block a thread in case the current count exceeds 'max'.
-    pub fn wait(&self) {
-        let mut locked = self.is_locked.lock().unwrap();
-        if *locked {
-            let lock_result = self.waiter.wait(locked);
-            locked = lock_result.unwrap();
+    #[test]
+    fn test_wait_queued_positions() {
+        let semaphore = Arc::new(NaiveSemaphore::new(1));
+        let held = semaphore.wait();
+
+        let (positions_tx, positions) = std::sync::mpsc::channel();
+        let (admit_tx, admit) = std::sync::mpsc::channel::<()>();
+        let admit = Arc::new(Mutex::new(admit));
+        let queued: Vec<_> = (0..3)
+            .map(|idx| {
+                let semaphore = Arc::clone(&semaphore);
+                let positions_tx = positions_tx.clone();
+                let admit = Arc::clone(&admit);
+                let handle = std::thread::spawn(move || {
+                    let queued = semaphore.wait_queued();
+                    positions_tx.send((idx, queued.position())).unwrap();
+                    let permit = queued.wait();
+                    admit.lock().unwrap().recv().unwrap();
+                    drop(permit);
+                });
+                // queue up one after another
+                assert_eq!(positions.recv().unwrap(), (idx, idx));
+                handle
+            })
+            .collect();
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while semaphore.waiting_count() < 3 {
+            assert!(Instant::now() < deadline, "the threads never blocked");
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        // a late arrival queues up behind all three, and moves up as they get in
+        let late = semaphore.wait_queued();
+        assert_eq!(late.position(), 3);
+        drop(held);
+        for ahead in (0..3).rev() {
+            while late.position() != ahead {
+                assert!(Instant::now() < deadline, "the queue never moved");
+                std::thread::sleep(Duration::from_millis(1));
+            }
+            admit_tx.send(()).unwrap();
         }
-        self.current.incr();
-        if self.current.get() >= self.max {
-            *locked = true;
+        for handle in queued {
+            handle.join().unwrap();
         }
+        let _permit = late.wait();
+        assert_eq!(semaphore.current_count(), 1);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::collections::HashMap;
-    use std::sync::Arc;
-    use stopwatch::Stopwatch;
+    #[test]
+    fn test_wait_queued_timeout_keeps_place() {
+        let semaphore = NaiveSemaphore::new(1);
+        let held = semaphore.wait();
+        let first = semaphore.wait_queued();
+        let second = semaphore.wait_queued();
 
-    fn aggregate_counts<'a>(
-        hm: &'a mut HashMap<char, usize>,
-        update_hm: &HashMap<char, usize>,
-    ) -> &'a HashMap<char, usize> {
-        for (c, ct) in update_hm.iter() {
-            if let Some(current_ct) = hm.get_mut(&c) {
-                *current_ct += ct;
-            } else {
-                hm.insert(*c, *ct);
+        let second = second
+            .wait_timeout(Duration::from_millis(10))
+            .err()
+            .unwrap();
+        assert_eq!(second.position(), 1);
+        // giving up the place moves everyone behind up
+        drop(first);
+        assert_eq!(second.position(), 0);
+        drop(held);
+        assert!(second.wait_timeout(Duration::from_millis(10)).is_ok());
+        assert_eq!(semaphore.waiting_count(), 0);
+    }
+
+    #[test]
+    fn test_wait_with_calls_back_on_wakeups() {
+        let semaphore = Arc::new(NaiveSemaphore::new(1));
+        let wakeups = Arc::new(AtomicUsize::new(0));
+        let held = semaphore.wait();
+
+        let waiting = {
+            let semaphore = Arc::clone(&semaphore);
+            let wakeups = Arc::clone(&wakeups);
+            std::thread::spawn(move || {
+                drop(semaphore.wait_with(|| {
+                    wakeups.fetch_add(1, Ordering::SeqCst);
+                }))
+            })
+        };
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while semaphore.waiting_count() == 0 {
+            assert!(Instant::now() < deadline, "the thread never blocked");
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        drop(held);
+        waiting.join().unwrap();
+        assert!(wakeups.load(Ordering::SeqCst) >= 1);
+
+        // under contention, every blocked round reports its wakeups
+        let wakeups = AtomicUsize::new(0);
+        std::thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..20 {
+                        let _permit = semaphore.wait_with(|| {
+                            wakeups.fetch_add(1, Ordering::SeqCst);
+                        });
+                        std::thread::sleep(Duration::from_micros(200));
+                    }
+                });
+            }
+        });
+        println!("{} wakeups", wakeups.load(Ordering::SeqCst));
+        assert!(wakeups.load(Ordering::SeqCst) > 0);
+        assert_eq!(semaphore.current_count(), 0);
+    }
+
+    #[test]
+    fn test_active_count_within_current_count() {
+        let semaphore = NaiveSemaphore::new(4);
+        let barrier = std::sync::Barrier::new(5);
+        std::thread::scope(|s| {
+            for idx in 0..4 {
+                let (semaphore, barrier) = (&semaphore, &barrier);
+                s.spawn(move || {
+                    for round in 0..20 {
+                        let mut permit = semaphore.wait();
+                        if (idx + round) % 3 == 0 {
+                            permit.mark_active();
+                        }
+                        barrier.wait();
+                        // the main thread checks both counts here
+                        barrier.wait();
+                        permit.mark_idle();
+                        if round % 4 == 0 {
+                            permit.mark_active();
+                        }
+                    }
+                });
+            }
+            for _ in 0..20 {
+                barrier.wait();
+                let active = semaphore.active_count();
+                let current = semaphore.current_count();
+                assert!(active <= current, "{} active of {}", active, current);
+                assert_eq!(current, 4);
+                barrier.wait();
             }
+        });
+        assert_eq!(semaphore.active_count(), 0);
+
+        // split and merged guards keep the count of active permits
+        let mut batch = semaphore.wait_n(3);
+        batch.mark_active();
+        let mut parts = batch.split();
+        assert_eq!(semaphore.active_count(), 3);
+        let mut merged = parts.pop().unwrap();
+        parts[0].mark_idle();
+        for part in parts {
+            merged.merge(part);
         }
-        hm
+        assert_eq!(semaphore.active_count(), 3);
+        drop(merged);
+        assert_eq!(semaphore.active_count(), 0);
     }
 
-    fn char_frequency(word: &str) -> HashMap<char, usize> {
-        let mut hm = HashMap::new();
-        for c in word.chars() {
-            if let Some(ct) = hm.get_mut(&c) {
-                *ct += 1;
-            } else {
-                hm.insert(c, 1);
+    #[test]
+    fn test_try_release() {
+        let semaphore = NaiveSemaphore::new(2);
+        semaphore.wait_no_guard();
+        assert_eq!(semaphore.try_release_n(2), Err(OverRelease));
+        assert_eq!(semaphore.current_count(), 1);
+        assert_eq!(semaphore.try_release_one(), Ok(()));
+        assert_eq!(semaphore.try_release_one(), Err(OverRelease));
+        assert_eq!(semaphore.current_count(), 0);
+    }
+
+    #[test]
+    fn test_concurrent_over_releases_never_wrap() {
+        let semaphore = NaiveSemaphore::new(4);
+        let barrier = std::sync::Barrier::new(4);
+        let wrapped = AtomicUsize::new(0);
+        std::thread::scope(|s| {
+            for idx in 0..4 {
+                let (semaphore, barrier, wrapped) = (&semaphore, &barrier, &wrapped);
+                s.spawn(move || {
+                    for _ in 0..500 {
+                        // a single permit taken, released by all four threads at once
+                        if idx == 0 {
+                            semaphore.wait_no_guard();
+                        }
+                        barrier.wait();
+                        semaphore.release_one();
+                        if semaphore.current_count() > semaphore.max_permits() {
+                            wrapped.fetch_add(1, Ordering::SeqCst);
+                        }
+                        barrier.wait();
+                    }
+                });
             }
+        });
+        assert_eq!(wrapped.load(Ordering::SeqCst), 0);
+        assert_eq!(semaphore.current_count(), 0);
+        assert_eq!(semaphore.available_permits(), 4);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "released 1 permit(s) while only 0 are taken")]
+    fn test_strict_rejects_double_release() {
+        let semaphore = NaiveSemaphore::strict(1);
+        semaphore.wait_no_guard();
+        semaphore.release_one();
+        semaphore.release_one();
+    }
+
+    #[test]
+    fn test_deadlock_handler() {
+        let reports = Arc::new(AtomicUsize::new(0));
+        let semaphore = {
+            let reports = Arc::clone(&reports);
+            Arc::new(NaiveSemaphore::with_deadlock_handler(
+                1,
+                Duration::from_millis(20),
+                move || {
+                    reports.fetch_add(1, Ordering::SeqCst);
+                },
+            ))
+        };
+
+        // the only permit holder blocks on a second permit, nothing can ever be released
+        let handle = {
+            let semaphore = Arc::clone(&semaphore);
+            std::thread::spawn(move || {
+                let _outer = semaphore.wait();
+                let _inner = semaphore.wait();
+            })
+        };
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while reports.load(Ordering::SeqCst) == 0 {
+            assert!(Instant::now() < deadline, "the deadlock was never reported");
+            std::thread::sleep(Duration::from_millis(1));
         }
-        hm
+        // the waiter keeps checking, but the same stall is not reported again
+        std::thread::sleep(Duration::from_millis(100));
+        assert_eq!(reports.load(Ordering::SeqCst), 1);
+        assert!(!handle.is_finished());
+
+        semaphore.release_all();
+        handle.join().unwrap();
+        assert_eq!(reports.load(Ordering::SeqCst), 1);
     }
 
-    // TODO: replace println's by async logger!
-    // TODO: check with spans and jaeger?
-    fn frequency(input: &'static [&str], worker_count: usize, slow_down: Option<u64>) -> usize {
-        let naive_semaphore = Arc::new(NaiveSemaphore::new(worker_count));
-        let mut handles = vec![];
+    #[test]
+    fn test_waiting_count() {
+        let semaphore = Arc::new(NaiveSemaphore::new(1));
+        let held = semaphore.wait();
+        assert_eq!(semaphore.waiting_count(), 0);
 
-        let results: HashMap<char, usize> = HashMap::new();
-        let sharedstate_res = Arc::new(Mutex::new(results));
+        let parked = 4;
+        let handles: Vec<_> = (0..parked)
+            .map(|_| {
+                let semaphore = Arc::clone(&semaphore);
+                std::thread::spawn(move || drop(semaphore.wait()))
+            })
+            .collect();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while semaphore.waiting_count() < parked {
+            assert!(
+                Instant::now() < deadline,
+                "the waiting count never reached {}",
+                parked
+            );
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(semaphore.waiting_count(), parked);
 
-        for (idx, word) in input.iter().enumerate() {
-            let semaphore = Arc::clone(&naive_semaphore);
-            let ss_res = Arc::clone(&sharedstate_res);
+        drop(held);
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(semaphore.waiting_count(), 0);
+    }
 
-            let handle = std::thread::spawn(move || {
-                let sw = Stopwatch::start_new();
-                println!("thread {} - cc {}: waiting", idx, semaphore.current_count());
+    #[test]
+    fn test_is_full_and_is_empty() {
+        let semaphore = NaiveSemaphore::new(2);
+        assert_eq!(semaphore.max_permits(), 2);
+        assert!(semaphore.is_empty());
+        assert!(!semaphore.is_full());
 
-                semaphore.wait();
+        let first = semaphore.wait();
+        assert!(!semaphore.is_empty());
+        assert!(!semaphore.is_full());
 
-                println!(
-                    "thread {} - cc {} - {} ms: starting work",
-                    idx,
-                    semaphore.current_count(),
-                    sw.elapsed_ms()
-                );
+        let second = semaphore.wait();
+        assert!(!semaphore.is_empty());
+        assert!(semaphore.is_full());
 
-                // just as a test to simulate a heavier workload
-                if let Some(ms) = slow_down {
-                    std::thread::sleep(std::time::Duration::from_millis(ms));
-                }
+        drop(first);
+        drop(second);
+        assert!(semaphore.is_empty());
+    }
 
-                let word_count = char_frequency(word);
+    #[test]
+    fn test_wait_cancellable() {
+        let semaphore = Arc::new(NaiveSemaphore::new(1));
+        let token = CancelToken::new();
+        let held = semaphore.wait();
 
-                {
-                    let res_lock = &mut ss_res.lock().unwrap();
-                    aggregate_counts(res_lock, &word_count);
-                }
+        let sem = Arc::clone(&semaphore);
+        let tok = token.clone();
+        let parked = std::thread::spawn(move || sem.wait_cancellable(&tok).map(|_| ()));
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(!parked.is_finished());
 
-                println!(
-                    "thread {} - cc {} - {} ms: work done",
-                    idx,
-                    semaphore.current_count(),
-                    sw.elapsed_ms()
-                );
+        let cancelled_at = Instant::now();
+        std::thread::spawn(move || token.cancel()).join().unwrap();
+        assert_eq!(parked.join().unwrap(), Err(AcquireError::Cancelled));
+        assert!(cancelled_at.elapsed() < Duration::from_secs(1));
+        // the cancelled wait took no permit
+        assert_eq!(semaphore.current_count(), 1);
+        drop(held);
+        assert_eq!(semaphore.current_count(), 0);
+    }
 
-                semaphore.release_one();
-                println!(
-                    "thread {} - cc {} - {} ms: released",
-                    idx,
-                    semaphore.current_count(),
-                    sw.elapsed_ms()
-                );
-            });
+    #[test]
+    fn test_permits_iterator() {
+        let max = 3;
+        let semaphore = NaiveSemaphore::new(max);
+        let running = AtomicUsize::new(0);
+        let peak = AtomicUsize::new(0);
 
-            handles.push(handle);
+        std::thread::scope(|s| {
+            for permit in semaphore.permits().take(24) {
+                let (running, peak) = (&running, &peak);
+                s.spawn(move || {
+                    let now_running = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    peak.fetch_max(now_running, Ordering::SeqCst);
+                    std::thread::sleep(Duration::from_millis(1));
+                    running.fetch_sub(1, Ordering::SeqCst);
+                    drop(permit);
+                });
+                assert!(semaphore.current_count() <= max);
+            }
+        });
+        assert!(peak.load(Ordering::SeqCst) <= max);
+        assert_eq!(semaphore.current_count(), 0);
+
+        semaphore.close();
+        assert!(semaphore.permits().next().is_none());
+    }
+
+    #[test]
+    fn test_close() {
+        let semaphore = Arc::new(NaiveSemaphore::new(1));
+        let held = semaphore.wait();
+
+        let parked: Vec<_> = (0..3)
+            .map(|_| {
+                let semaphore = Arc::clone(&semaphore);
+                std::thread::spawn(move || semaphore.wait_or_closed().map(|_| ()))
+            })
+            .collect();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while semaphore.waiting_count() < 3 {
+            assert!(Instant::now() < deadline, "the waiters never parked");
+            std::thread::sleep(Duration::from_millis(1));
         }
 
-        let num_threads = handles.len();
+        semaphore.close();
+        for handle in parked {
+            assert_eq!(handle.join().unwrap(), Err(AcquireError::Closed));
+        }
+        assert!(semaphore.is_closed());
+        assert_eq!(semaphore.wait_or_closed().err(), Some(AcquireError::Closed));
 
-        for handle in handles {
-            handle.join().unwrap();
+        // the permit held across closing is still released normally
+        assert_eq!(semaphore.current_count(), 1);
+        drop(held);
+        assert_eq!(semaphore.current_count(), 0);
+
+        semaphore.reset();
+        assert!(semaphore.wait_or_closed().is_ok());
+    }
+
+    #[test]
+    fn test_close_releases_wait_timeout() {
+        let semaphore = Arc::new(NaiveSemaphore::new(1));
+        let held = semaphore.wait();
+
+        let parked: Vec<_> = (0..3)
+            .map(|_| {
+                let semaphore = Arc::clone(&semaphore);
+                std::thread::spawn(move || {
+                    semaphore.wait_timeout(Duration::from_secs(60)).map(|_| ())
+                })
+            })
+            .collect();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while semaphore.waiting_count() < 3 {
+            assert!(Instant::now() < deadline, "the waiters never parked");
+            std::thread::sleep(Duration::from_millis(1));
         }
 
-        dbg!(&sharedstate_res.lock().unwrap());
-        num_threads
+        // released long before their timeout
+        semaphore.close();
+        for handle in parked {
+            assert_eq!(handle.join().unwrap(), Err(AcquireError::Closed));
+        }
+        assert_eq!(semaphore.waiting_count(), 0);
+        drop(held);
+        assert_eq!(
+            semaphore.wait_timeout(Duration::ZERO).err(),
+            Some(AcquireError::Closed)
+        );
     }
 
-    // Poem by Friedrich Schiller. The corresponding music is the European Anthem.
-    const ODE_AN_DIE_FREUDE: [&str; 32] = [
-        "Freude schöner Götterfunken",
-        "Tochter aus Elysium,",
-        "Wir betreten feuertrunken,",
-        "Himmlische, dein Heiligtum!",
-        "Deine Zauber binden wieder",
-        "Was die Mode streng geteilt;",
-        "Alle Menschen werden Brüder,",
-        "Wo dein sanfter Flügel weilt.",
-        "Freude schöner Götterfunken",
-        "Tochter aus Elysium,",
-        "Wir betreten feuertrunken,",
-        "Himmlische, dein Heiligtum!",
-        "Deine Zauber binden wieder",
-        "Was die Mode streng geteilt;",
-        "Alle Menschen werden Brüder,",
-        "Wo dein sanfter Flügel weilt.",
-        "Freude schöner Götterfunken",
-        "Tochter aus Elysium,",
-        "Wir betreten feuertrunken,",
-        "Himmlische, dein Heiligtum!",
-        "Deine Zauber binden wieder",
-        "Was die Mode streng geteilt;",
-        "Alle Menschen werden Brüder,",
-        "Wo dein sanfter Flügel weilt.",
-        "Freude schöner Götterfunken",
-        "Tochter aus Elysium,",
-        "Wir betreten feuertrunken,",
-        "Himmlische, dein Heiligtum!",
-        "Deine Zauber binden wieder",
-        "Was die Mode streng geteilt;",
-        "Alle Menschen werden Brüder,",
-        "Wo dein sanfter Flügel weilt.",
-    ];
+    #[test]
+    fn test_acquire_error() {
+        // every fallible acquisition fails with the variant of its scenario
+        let semaphore = NaiveSemaphore::with_max_waiters(1, 0);
+        let held = semaphore.wait();
+        assert_eq!(
+            semaphore.try_acquire().err(),
+            Some(AcquireError::WouldBlock)
+        );
+        assert_eq!(
+            semaphore.wait_timeout(Duration::from_millis(5)).err(),
+            Some(AcquireError::TimedOut)
+        );
+        assert_eq!(
+            semaphore.wait_or_overloaded().err(),
+            Some(AcquireError::Overloaded)
+        );
+        drop(held);
+
+        let token = CancelToken::new();
+        token.cancel();
+        assert_eq!(
+            semaphore.wait_cancellable(&token).err(),
+            Some(AcquireError::Cancelled)
+        );
+
+        let reentrant = NaiveSemaphore::with_reentrancy_check(1);
+        let _held = reentrant.wait();
+        assert_eq!(
+            reentrant.wait_or_would_deadlock().err(),
+            Some(AcquireError::WouldDeadlock)
+        );
+
+        semaphore.close();
+        assert_eq!(semaphore.wait_or_closed().err(), Some(AcquireError::Closed));
+        assert_eq!(semaphore.try_acquire().err(), Some(AcquireError::Closed));
+
+        // one error type for `?` across all of them
+        fn acquire_all(semaphore: &NaiveSemaphore) -> Result<usize, AcquireError> {
+            let first = semaphore.wait_timeout(Duration::from_millis(5))?;
+            let second = semaphore.wait_cancellable(&CancelToken::new())?;
+            let third = semaphore.wait_or_closed()?;
+            let fourth = semaphore.try_acquire()?;
+            Ok(first.permits() + second.permits() + third.permits() + fourth.permits())
+        }
+        assert_eq!(acquire_all(&NaiveSemaphore::new(4)), Ok(4));
+        assert_eq!(
+            acquire_all(&NaiveSemaphore::new(3)),
+            Err(AcquireError::WouldBlock)
+        );
+
+        for (error, message) in [
+            (
+                AcquireError::WouldBlock,
+                "no semaphore permit is free right now",
+            ),
+            (
+                AcquireError::TimedOut,
+                "timed out waiting for a semaphore permit",
+            ),
+            (AcquireError::Closed, "the semaphore was closed"),
+            (
+                AcquireError::Cancelled,
+                "cancelled waiting for a semaphore permit",
+            ),
+            (
+                AcquireError::Overloaded,
+                "too many threads are waiting for a semaphore permit",
+            ),
+            (
+                AcquireError::WouldDeadlock,
+                "the thread already holds a permit of the full semaphore",
+            ),
+        ] {
+            assert_eq!(error.to_string(), message);
+        }
+    }
+
+    #[test]
+    fn test_reset() {
+        let semaphore = NaiveSemaphore::new(3);
+        std::mem::forget(semaphore.wait_indexed());
+        std::mem::forget(semaphore.wait());
+        assert_eq!(semaphore.current_count(), 2);
+
+        semaphore.reset();
+        assert_eq!(semaphore.current_count(), 0);
+        assert_eq!(semaphore.available_permits(), semaphore.max_permits());
+        assert_eq!(semaphore.wait_indexed().slot(), 0);
+    }
+
+    #[test]
+    fn test_reset_while_indexed_permit_held() {
+        let semaphore = NaiveSemaphore::new(2);
+        let first = semaphore.wait_indexed();
+        let second = semaphore.wait_indexed();
+        assert_eq!(second.slot(), 1);
+
+        semaphore.reset();
+        // the index of a permit held across the reset is handed out again
+        let after = semaphore.wait_indexed();
+        assert_eq!(after.slot(), 0);
+
+        // dropping the permits from before the reset leaves the new holder's slot taken
+        drop(first);
+        drop(second);
+        let next = semaphore.wait_indexed();
+        assert_eq!(next.slot(), 1);
+        drop((after, next));
+        assert_eq!(semaphore.current_count(), 0);
+        assert_eq!(semaphore.wait_indexed().slot(), 0);
+    }
+
+    #[test]
+    fn test_debug() {
+        let semaphore = NaiveSemaphore::new(3);
+        let permit = semaphore.wait();
+        // the lock is held by the caller, formatting must not wait for it
+        let _parking = semaphore.lock();
+
+        let formatted = format!("{:?}", semaphore);
+        assert!(formatted.contains("max: 3"), "{}", formatted);
+        assert!(formatted.contains("current_count: 1"), "{}", formatted);
+        assert!(formatted.contains("available_permits: 2"), "{}", formatted);
+        assert!(formatted.contains("locked: false"), "{}", formatted);
+        drop(permit);
+    }
+
+    #[test]
+    fn test_uncontended_wait_takes_no_lock() {
+        let semaphore = NaiveSemaphore::new(2);
+        // the lock is not reentrant, taking it below would deadlock
+        let _parking = semaphore.lock();
+
+        let permit = semaphore.wait();
+        let second = semaphore.try_wait().unwrap();
+        assert!(semaphore.try_wait().is_none());
+        drop(permit);
+        drop(second);
+        assert_eq!(semaphore.current_count(), 0);
+    }
+
+    #[test]
+    fn test_fast_path_under_contention() {
+        let max = 4;
+        let semaphore = Arc::new(NaiveSemaphore::new(max));
+        let running = Arc::new(AtomicUsize::new(0));
+        let (done_tx, done_rx) = std::sync::mpsc::channel();
+
+        let threads = 16;
+        for idx in 0..threads {
+            let semaphore = Arc::clone(&semaphore);
+            let running = Arc::clone(&running);
+            let done_tx = done_tx.clone();
+            std::thread::spawn(move || {
+                for round in 0..2_000 {
+                    // mix the fast path, parking and timeouts
+                    let permit = match (idx + round) % 3 {
+                        0 => Some(semaphore.wait()),
+                        1 => semaphore.try_wait(),
+                        _ => semaphore.wait_timeout(Duration::from_millis(1)).ok(),
+                    };
+                    if permit.is_some() {
+                        let now_running = running.fetch_add(1, Ordering::SeqCst) + 1;
+                        assert!(now_running <= max);
+                        running.fetch_sub(1, Ordering::SeqCst);
+                    }
+                }
+                done_tx.send(()).unwrap();
+            });
+        }
+
+        // a lost wakeup leaves a thread parked for good
+        for _ in 0..threads {
+            done_rx
+                .recv_timeout(Duration::from_secs(30))
+                .expect("a thread stayed parked next to a free permit");
+        }
+        assert_eq!(semaphore.current_count(), 0);
+    }
 
     #[test]
     #[ignore]
     fn test_freq_count_slow() {
         let mut sw = stopwatch::Stopwatch::start_new();
 
-        let num_done = frequency(&ODE_AN_DIE_FREUDE, 5, Some(2));
+        let stdout = Arc::new(|message: &str| println!("{}", message));
+        let num_done = frequency_logged(&ODE_AN_DIE_FREUDE, 5, Some(2), stdout);
 
         sw.stop();
         println!("done within {} ms", sw.elapsed_ms());
@@ -231,12 +4918,133 @@ mod tests {
 
     #[test]
     fn test_freq_count() {
-        let mut sw = stopwatch::Stopwatch::start_new();
-
         let num_done = frequency(&ODE_AN_DIE_FREUDE, 9, None);
+        assert_eq!(num_done, 32);
+    }
 
-        sw.stop();
-        println!("done within {} ms", sw.elapsed_ms());
+    #[test]
+    fn test_freq_count_logged() {
+        let messages = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&messages);
+        let logger = Arc::new(move |message: &str| {
+            recorded.lock().unwrap().push(message.to_string());
+        });
+
+        let num_done = frequency_logged(&ODE_AN_DIE_FREUDE, 9, None, logger);
         assert_eq!(num_done, 32);
+
+        // four per thread and the aggregated counts, all of them through the logger
+        let messages = messages.lock().unwrap();
+        assert_eq!(messages.len(), 4 * 32 + 1);
+        assert_eq!(
+            messages
+                .iter()
+                .filter(|m| m.ends_with(": released"))
+                .count(),
+            32
+        );
+    }
+}
+
+/// Model tests exploring the interleavings of the lock-free fast paths and parking, run with
+/// `RUSTFLAGS="--cfg loom" cargo test --release --lib loom_tests`.
+#[cfg(all(test, loom))]
+mod loom_tests {
+    use super::*;
+    use loom::thread;
+
+    fn model(test: impl Fn() + Sync + Send + 'static) {
+        let mut builder = loom::model::Builder::new();
+        builder.preemption_bound = Some(3);
+        builder.check(test);
+    }
+
+    #[test]
+    fn loom_concurrency_never_exceeds_max() {
+        model(|| {
+            let semaphore = Arc::new(NaiveSemaphore::new(1));
+            let running = Arc::new(AtomicUsize::new(0));
+
+            let handles: Vec<_> = (0..2)
+                .map(|_| {
+                    let semaphore = Arc::clone(&semaphore);
+                    let running = Arc::clone(&running);
+                    thread::spawn(move || {
+                        let _permit = semaphore.wait();
+                        assert_eq!(running.fetch_add(1, Ordering::SeqCst), 0);
+                        running.fetch_sub(1, Ordering::SeqCst);
+                    })
+                })
+                .collect();
+
+            for handle in handles {
+                handle.join().unwrap();
+            }
+            assert_eq!(semaphore.current_count(), 0);
+        });
+    }
+
+    #[test]
+    #[allow(unsafe_code)] // loom's `UnsafeCell` is how the model detects data races
+    fn loom_critical_sections_do_not_race() {
+        model(|| {
+            let semaphore = Arc::new(NaiveSemaphore::new(1));
+            // loom reports a data race unless taking a permit acquires the previous release
+            let shared = Arc::new(loom::cell::UnsafeCell::new(0));
+
+            let handles: Vec<_> = (0..2)
+                .map(|_| {
+                    let semaphore = Arc::clone(&semaphore);
+                    let shared = Arc::clone(&shared);
+                    thread::spawn(move || {
+                        let _permit = semaphore.wait();
+                        shared.with_mut(|value| unsafe { *value += 1 });
+                    })
+                })
+                .collect();
+
+            for handle in handles {
+                handle.join().unwrap();
+            }
+            assert_eq!(shared.with(|value| unsafe { *value }), 2);
+        });
+    }
+
+    #[test]
+    fn loom_release_wakes_parked_waiter() {
+        model(|| {
+            let semaphore = Arc::new(NaiveSemaphore::new(1));
+            semaphore.wait_no_guard();
+
+            let sem = Arc::clone(&semaphore);
+            // loom reports a deadlock if the release is lost while this thread parks
+            let waiter = thread::spawn(move || drop(sem.wait()));
+
+            semaphore.release_one();
+            waiter.join().unwrap();
+            assert_eq!(semaphore.current_count(), 0);
+        });
+    }
+
+    #[test]
+    fn loom_release_n_wakes_bulk_and_single_waiters() {
+        model(|| {
+            let semaphore = Arc::new(NaiveSemaphore::new(2));
+            semaphore.wait_for_slots(2);
+
+            let bulk = {
+                let semaphore = Arc::clone(&semaphore);
+                thread::spawn(move || drop(semaphore.wait_n(2)))
+            };
+            let single = {
+                let semaphore = Arc::clone(&semaphore);
+                thread::spawn(move || drop(semaphore.wait()))
+            };
+
+            semaphore.release_n(2);
+            bulk.join().unwrap();
+            single.join().unwrap();
+            assert_eq!(semaphore.current_count(), 0);
+        });
     }
 }
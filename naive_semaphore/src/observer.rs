@@ -0,0 +1,19 @@
+/// Hooks into the lifecycle of a `NaiveSemaphore`, e.g. to feed contention into a metrics
+/// exporter, see `NaiveSemaphore::with_observer`. All methods default to doing nothing.
+///
+/// The hooks may run concurrently on several threads, some of them while the semaphore's
+/// internal lock is held: keep them cheap and never call back into the same semaphore from
+/// within a hook.
+pub trait SemaphoreObserver: Send + Sync {
+    /// A thread found no free permit and starts blocking.
+    fn on_wait_start(&self) {}
+
+    /// 'permits' permits were acquired.
+    fn on_acquired(&self, _permits: usize) {}
+
+    /// 'permits' permits were released.
+    fn on_released(&self, _permits: usize) {}
+
+    /// A thread gave up waiting for a permit.
+    fn on_timeout(&self) {}
+}
@@ -0,0 +1,89 @@
+use crate::naive_semaphore::NaiveSemaphore;
+
+/// Map 'f' over 'items' on scoped threads, at most 'max_concurrency' of them alive at a time.
+/// The results are in the order of 'items'.
+///
+/// If 'f' panics for any item, the panic is propagated once all threads were joined.
+///
+/// # Panics
+///
+/// Panics if 'max_concurrency' is zero, or if 'f' panics.
+///
+/// ```
+/// use naive_semaphore::parallel_map;
+///
+/// let lengths = parallel_map(vec!["a", "bb", "ccc"], 2, |word| word.len());
+/// assert_eq!(lengths, [1, 2, 3]);
+/// ```
+pub fn parallel_map<T, R, F>(items: Vec<T>, max_concurrency: usize, f: F) -> Vec<R>
+where
+    T: Send,
+    R: Send,
+    F: Fn(T) -> R + Sync,
+{
+    let semaphore = NaiveSemaphore::new(max_concurrency);
+    let f = &f;
+    let results: Vec<_> = semaphore.scope(|s| {
+        let handles: Vec<_> = items
+            .into_iter()
+            .map(|item| s.spawn(move || f(item)))
+            .collect();
+        handles.into_iter().map(|handle| handle.join()).collect()
+    });
+    results
+        .into_iter()
+        .map(|result| result.unwrap_or_else(|panic| std::panic::resume_unwind(panic)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // The first verse of Friedrich Schiller's poem.
+    const VERSE: [&str; 8] = [
+        "Freude schöner Götterfunken",
+        "Tochter aus Elysium,",
+        "Wir betreten feuertrunken,",
+        "Himmlische, dein Heiligtum!",
+        "Deine Zauber binden wieder",
+        "Was die Mode streng geteilt;",
+        "Alle Menschen werden Brüder,",
+        "Wo dein sanfter Flügel weilt.",
+    ];
+
+    fn char_frequency(line: &str) -> HashMap<char, usize> {
+        let mut hm = HashMap::new();
+        for c in line.chars() {
+            *hm.entry(c).or_insert(0) += 1;
+        }
+        hm
+    }
+
+    #[test]
+    fn test_parallel_map_keeps_order() {
+        let max_concurrency = 3;
+        let running = AtomicUsize::new(0);
+
+        let counts = parallel_map(VERSE.to_vec(), max_concurrency, |line| {
+            assert!(running.fetch_add(1, Ordering::SeqCst) < max_concurrency);
+            let counts = char_frequency(line);
+            running.fetch_sub(1, Ordering::SeqCst);
+            counts
+        });
+
+        let expected: Vec<_> = VERSE.iter().map(|line| char_frequency(line)).collect();
+        assert_eq!(counts, expected);
+    }
+
+    #[test]
+    #[should_panic(expected = "no umlauts")]
+    fn test_parallel_map_propagates_panics() {
+        parallel_map(VERSE.to_vec(), 2, |line| {
+            assert!(!line.contains('ü'), "no umlauts");
+            line.len()
+        });
+    }
+}
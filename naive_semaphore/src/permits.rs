@@ -0,0 +1,273 @@
+#[cfg(not(loom))]
+use core::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(loom)]
+use loom::sync::atomic::{AtomicUsize, Ordering};
+
+/// The count of taken permits occupies the low half of the packed state, the generation the
+/// high half. A `usize` keeps the state within the atomics of every target, 64-bit ones are
+/// missing on e.g. `thumbv7m` or `riscv32imc`.
+const COUNT_BITS: u32 = usize::BITS / 2;
+const COUNT_MASK: usize = (1 << COUNT_BITS) - 1;
+
+/// The most permits that can be taken at once, the count has to fit its half of the state.
+pub const MAX_TAKEN: usize = COUNT_MASK;
+
+/// Pack 'count' next to 'generation', which takes the high bits. Bits of 'generation' beyond
+/// its half are dropped, so it wraps around.
+fn pack(generation: usize, count: usize) -> usize {
+    debug_assert!(count <= MAX_TAKEN);
+    (generation << COUNT_BITS) | count
+}
+
+/// Split a packed state into its generation and count.
+fn unpack(state: usize) -> (usize, usize) {
+    (state >> COUNT_BITS, state & COUNT_MASK)
+}
+
+/// The counting logic shared by all semaphores of this crate: the capacity, the count of
+/// currently taken permits and the admission check, all lock-free and usable without `std`.
+///
+/// It never blocks, how a thread waits for a permit is left to the semaphore built on top.
+///
+/// Taking permits is an `Acquire` and giving them back a `Release`, which is all a critical
+/// section needs: whatever a holder did before releasing happens before the next holder
+/// starts. Plain reads are `Relaxed`, they are snapshots or the first guess of a
+/// compare-and-swap that fails on anything outdated. The capacity is `Relaxed` throughout,
+/// semaphores changing it while threads wait do so under their own lock.
+///
+/// The count shares one `AtomicUsize` with a generation bumped by every change, so a
+/// compare-and-swap from an outdated snapshot fails even if the count came back to the same
+/// value in between (ABA), unless the generation wrapped around all the way in between. At
+/// most `MAX_TAKEN` permits can be taken at a time, which is 65535 on 32-bit targets.
+pub struct Permits {
+    max: AtomicUsize,
+    state: AtomicUsize,
+}
+
+impl Permits {
+    #[cfg(not(loom))]
+    pub const fn new(max: usize) -> Self {
+        Self {
+            max: AtomicUsize::new(max),
+            state: AtomicUsize::new(0),
+        }
+    }
+
+    /// The loom atomics cannot be created in a const context.
+    #[cfg(loom)]
+    pub fn new(max: usize) -> Self {
+        Self {
+            max: AtomicUsize::new(max),
+            state: AtomicUsize::new(0),
+        }
+    }
+
+    /// The capacity.
+    pub fn max(&self) -> usize {
+        self.max.load(Ordering::Relaxed)
+    }
+
+    /// The count of currently taken permits.
+    pub fn current(&self) -> usize {
+        unpack(self.snapshot()).1
+    }
+
+    /// The packed generation and count.
+    fn snapshot(&self) -> usize {
+        self.state.load(Ordering::Relaxed)
+    }
+
+    /// Set the count to 'count' unless the state changed since 'seen', starting the next
+    /// generation. Returns the current state on failure, to retry with.
+    fn update(&self, seen: usize, count: usize, success: Ordering) -> Result<(), usize> {
+        let next = pack(unpack(seen).0.wrapping_add(1), count);
+        self.state
+            .compare_exchange_weak(seen, next, success, Ordering::Relaxed)
+            .map(|_| ())
+    }
+
+    /// Whether 'n' more permits could be taken right now.
+    pub fn has_room(&self, n: usize) -> bool {
+        n <= self.max().saturating_sub(self.current())
+    }
+
+    /// Take 'n' permits if there is room for all of them. Check and increment form a single
+    /// compare-and-swap, so concurrent callers can never overshoot the capacity together.
+    pub fn try_acquire(&self, n: usize) -> bool {
+        let mut seen = self.snapshot();
+        loop {
+            let current = unpack(seen).1;
+            if n > self.max().min(MAX_TAKEN).saturating_sub(current) {
+                return false;
+            }
+            match self.update(seen, current + n, Ordering::Acquire) {
+                Ok(()) => return true,
+                Err(actual) => seen = actual,
+            }
+        }
+    }
+
+    /// Give back up to 'n' permits, never dropping below zero.
+    /// Returns the count of permits actually given back.
+    pub fn release(&self, n: usize) -> usize {
+        let mut seen = self.snapshot();
+        loop {
+            let current = unpack(seen).1;
+            let released = n.min(current);
+            if released == 0 {
+                return 0;
+            }
+            match self.update(seen, current - released, Ordering::Release) {
+                Ok(()) => return released,
+                Err(actual) => seen = actual,
+            }
+        }
+    }
+
+    /// Give back exactly 'n' permits, or none at all if fewer than 'n' are taken.
+    pub fn try_release(&self, n: usize) -> bool {
+        let mut seen = self.snapshot();
+        loop {
+            let current = unpack(seen).1;
+            if current < n {
+                return false;
+            }
+            match self.update(seen, current - n, Ordering::Release) {
+                Ok(()) => return true,
+                Err(actual) => seen = actual,
+            }
+        }
+    }
+
+    /// Take 'n' permits regardless of the capacity, e.g. ones carried over from elsewhere.
+    ///
+    /// # Panics
+    ///
+    /// Panics if more than `MAX_TAKEN` permits would be taken.
+    pub fn take(&self, n: usize) {
+        let mut seen = self.snapshot();
+        loop {
+            let current = unpack(seen).1;
+            let taken = current
+                .checked_add(n)
+                .filter(|&taken| taken <= MAX_TAKEN)
+                .expect("more than MAX_TAKEN permits taken");
+            match self.update(seen, taken, Ordering::Acquire) {
+                Ok(()) => return,
+                Err(actual) => seen = actual,
+            }
+        }
+    }
+
+    /// Replace the capacity, returning the previous one.
+    pub fn set_max(&self, max: usize) -> usize {
+        self.max.swap(max, Ordering::Relaxed)
+    }
+
+    /// Increase the capacity by 'n'.
+    pub fn add_max(&self, n: usize) {
+        self.max.fetch_add(n, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_try_acquire_and_release() {
+        let permits = Permits::new(3);
+        assert!(permits.try_acquire(2));
+        assert!(!permits.try_acquire(2));
+        assert!(permits.try_acquire(1));
+        assert!(!permits.has_room(1));
+
+        assert!(!permits.try_release(4));
+        assert!(permits.try_release(1));
+        assert_eq!(permits.release(5), 2);
+        assert_eq!(permits.release(1), 0);
+        assert_eq!(permits.current(), 0);
+    }
+
+    #[test]
+    fn test_pack_unpack() {
+        assert_eq!(unpack(pack(0, 0)), (0, 0));
+        assert_eq!(unpack(pack(7, 3)), (7, 3));
+        let last = usize::MAX >> COUNT_BITS;
+        assert_eq!(unpack(pack(last, MAX_TAKEN)), (last, MAX_TAKEN));
+    }
+
+    #[test]
+    fn test_generation_wraps_around() {
+        let last = usize::MAX >> COUNT_BITS;
+        assert_eq!(unpack(pack(last.wrapping_add(1), 3)), (0, 3));
+
+        let permits = Permits::new(1);
+        permits.state.store(pack(last, 0), Ordering::Relaxed);
+        assert!(permits.try_acquire(1));
+        assert_eq!(unpack(permits.snapshot()), (0, 1));
+    }
+
+    #[test]
+    fn test_outdated_snapshot_fails_despite_same_count() {
+        let permits = Permits::new(1);
+        let seen = permits.snapshot();
+        assert!(permits.try_acquire(1));
+        assert!(permits.try_release(1));
+
+        // the count is back to zero, but two generations later
+        assert_eq!(permits.current(), unpack(seen).1);
+        assert_eq!(unpack(permits.snapshot()).0, unpack(seen).0 + 2);
+        assert!(permits
+            .state
+            .compare_exchange(seen, pack(1, 1), Ordering::Acquire, Ordering::Relaxed)
+            .is_err());
+    }
+
+    #[test]
+    fn test_count_bounded_by_max_taken() {
+        let permits = Permits::new(usize::MAX);
+        assert!(permits.try_acquire(MAX_TAKEN));
+        assert!(!permits.try_acquire(1));
+        assert_eq!(permits.release(usize::MAX), MAX_TAKEN);
+    }
+}
+
+/// Model tests of the packed state, run together with the ones in `naive_semaphore.rs`.
+#[cfg(all(test, loom))]
+mod loom_tests {
+    use super::*;
+    use loom::sync::Arc;
+    use loom::thread;
+
+    #[test]
+    fn loom_outdated_snapshot_never_wins() {
+        loom::model(|| {
+            let permits = Arc::new(Permits::new(2));
+            let seen = permits.snapshot();
+
+            // an acquire and release bringing the count back to where it was
+            let other = Arc::clone(&permits);
+            let handle = thread::spawn(move || {
+                assert!(other.try_acquire(1));
+                assert!(other.try_release(1));
+            });
+
+            // a compare-and-swap from the snapshot only succeeds before the other thread
+            // changed anything, once it did the count may match but the generation does not
+            let won = permits
+                .state
+                .compare_exchange(seen, pack(1, 1), Ordering::Acquire, Ordering::Relaxed)
+                .is_ok();
+            if won {
+                assert!(permits.try_release(1));
+            }
+            handle.join().unwrap();
+
+            assert_eq!(permits.current(), 0);
+            // a win after the other thread's round trip would have reset the generation to 1
+            let generation = unpack(permits.snapshot()).0;
+            assert_eq!(generation, if won { 4 } else { 2 });
+        });
+    }
+}
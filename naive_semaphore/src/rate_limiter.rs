@@ -0,0 +1,146 @@
+use crate::error::InvalidCapacity;
+use crate::sync::{Condvar, Mutex};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// A rate limiter admitting at most 'max' acquisitions per rolling 'window', e.g. to throttle
+/// outbound requests. Unlike the permits of a `NaiveSemaphore` nothing is released: each
+/// acquisition counts against the limit until 'window' passed since it was granted.
+///
+/// ```
+/// use naive_semaphore::RateLimiter;
+/// use std::time::Duration;
+///
+/// let limiter = RateLimiter::new(2, Duration::from_secs(60));
+/// limiter.acquire();
+/// assert!(limiter.try_acquire());
+/// assert!(!limiter.try_acquire());
+/// ```
+pub struct RateLimiter {
+    max: usize,
+    window: Duration,
+    // When the acquisitions still counting against the limit were granted, oldest first.
+    granted: Mutex<VecDeque<Instant>>,
+    // Waited on until the oldest acquisition expires, nothing frees up earlier to notify it.
+    waiter: Condvar,
+}
+
+impl RateLimiter {
+    /// Create a rate limiter admitting at most 'max' acquisitions per 'window'.
+    ///
+    /// # Panics
+    ///
+    /// Panics if 'max' is zero, see `try_new`.
+    pub fn new(max: usize, window: Duration) -> Self {
+        Self::try_new(max, window).expect("a rate limiter needs a capacity of at least one")
+    }
+
+    /// Create a rate limiter admitting at most 'max' acquisitions per 'window'.
+    /// Fails for a 'max' of zero, since such a limiter could never admit anything.
+    pub fn try_new(max: usize, window: Duration) -> Result<Self, InvalidCapacity> {
+        if max == 0 {
+            return Err(InvalidCapacity);
+        }
+        Ok(Self {
+            max,
+            window,
+            granted: Mutex::new(VecDeque::with_capacity(max)),
+            waiter: Condvar::new(),
+        })
+    }
+
+    /// The count of acquisitions that could be granted right now without waiting.
+    pub fn available(&self) -> usize {
+        let mut granted = self.granted.lock();
+        self.expire(&mut granted, Instant::now());
+        self.max - granted.len()
+    }
+
+    /// Block until the rate allows another acquisition and count it.
+    pub fn acquire(&self) {
+        let mut granted = self.granted.lock();
+        loop {
+            let now = Instant::now();
+            self.expire(&mut granted, now);
+            if granted.len() < self.max {
+                granted.push_back(now);
+                return;
+            }
+            // the oldest acquisition stops counting first
+            let free_at = granted[0] + self.window;
+            granted = self.waiter.wait_timeout(granted, free_at - now);
+        }
+    }
+
+    /// Count an acquisition only if the rate allows one right now, never blocks.
+    pub fn try_acquire(&self) -> bool {
+        let mut granted = self.granted.lock();
+        let now = Instant::now();
+        self.expire(&mut granted, now);
+        if granted.len() < self.max {
+            granted.push_back(now);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Drop the acquisitions granted a full 'window' ago or earlier.
+    fn expire(&self, granted: &mut VecDeque<Instant>, now: Instant) {
+        while granted
+            .front()
+            .is_some_and(|&at| now.duration_since(at) >= self.window)
+        {
+            granted.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_at_most_max_per_window() {
+        let (max, window) = (3, Duration::from_millis(100));
+        let limiter = Arc::new(RateLimiter::new(max, window));
+        let start = Instant::now();
+
+        let handles: Vec<_> = (0..3)
+            .map(|_| {
+                let limiter = Arc::clone(&limiter);
+                std::thread::spawn(move || {
+                    (0..3)
+                        .map(|_| {
+                            limiter.acquire();
+                            Instant::now()
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        let completed: Vec<_> = handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect();
+
+        // each acquisition completes after it was granted, so these were granted in the window
+        let in_first_window = completed.iter().filter(|&&at| at < start + window).count();
+        assert!(in_first_window <= max, "{} in one window", in_first_window);
+        // nine acquisitions at three per window take more than two windows
+        assert!(start.elapsed() >= window * 2);
+        assert_eq!(limiter.available(), 0);
+    }
+
+    #[test]
+    fn test_try_acquire_replenishes() {
+        let limiter = RateLimiter::new(1, Duration::from_millis(20));
+        assert!(limiter.try_acquire());
+        assert!(!limiter.try_acquire());
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(limiter.available(), 1);
+        assert!(limiter.try_acquire());
+        assert!(RateLimiter::try_new(0, Duration::ZERO).is_err());
+    }
+}
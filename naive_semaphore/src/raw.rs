@@ -0,0 +1,219 @@
+//! A semaphore generic over how waiting threads park, usable without `std`.
+//!
+//! `RawSemaphore` shares the counting logic of `Permits` with `NaiveSemaphore`, but instead of
+//! a `Mutex`/`Condvar` pair it delegates waiting to a `Parker`. With `default-features = false`
+//! this is the only semaphore available, and compared to `NaiveSemaphore` it
+//!
+//! - spins (with `SpinParker`) or does whatever the parker does instead of blocking the thread,
+//! - makes no fairness guarantee at all, whoever re-checks first after a release wins,
+//! - has no timeouts, observers or statistics, which would need a clock or allocation.
+//!
+//! A critical-section or RTOS based backend plugs in by implementing `Parker`.
+
+use crate::error::InvalidCapacity;
+use crate::permits::Permits;
+
+/// How a thread waits for a permit of a `RawSemaphore` to become free.
+///
+/// `park` may return spuriously, the semaphore re-checks for room in a loop. An `unpark_all`
+/// issued after a waiter's failed check must make its pending or next `park` return, like the
+/// token of `std::thread::park`, otherwise that waiter could miss the release.
+pub trait Parker {
+    /// Wait until a permit may have been released.
+    fn park(&self);
+
+    /// Permits were released, let parked threads re-check.
+    fn unpark_all(&self);
+}
+
+/// Busy-waits with `core::hint::spin_loop`, needs no operating system support at all.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SpinParker;
+
+impl Parker for SpinParker {
+    fn park(&self) {
+        core::hint::spin_loop();
+    }
+
+    fn unpark_all(&self) {}
+}
+
+/// A counting semaphore admitting at most 'max' threads at a time, parking via 'P'.
+pub struct RawSemaphore<P = SpinParker> {
+    permits: Permits,
+    parker: P,
+}
+
+/// A `RawSemaphore` that busy-waits for permits.
+pub type SpinSemaphore = RawSemaphore<SpinParker>;
+
+impl<P: Parker + Default> RawSemaphore<P> {
+    /// Create a semaphore admitting at most 'max' threads at a time.
+    ///
+    /// # Panics
+    ///
+    /// Panics if 'max' is zero, see `try_with_parker`.
+    pub fn new(max: usize) -> Self {
+        Self::with_parker(max, P::default())
+    }
+}
+
+impl SpinSemaphore {
+    /// Like `new`, but usable in a `const` context, e.g. for a semaphore in a `static`:
+    ///
+    /// ```
+    /// use naive_semaphore::SpinSemaphore;
+    ///
+    /// static DEVICES: SpinSemaphore = SpinSemaphore::new_const(2);
+    ///
+    /// let _permit = DEVICES.wait();
+    /// assert_eq!(DEVICES.available_permits(), 1);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if 'max' is zero, at compile time when evaluated in a `const` context.
+    #[cfg(not(loom))]
+    pub const fn new_const(max: usize) -> Self {
+        assert!(max > 0, "a semaphore needs a capacity of at least one");
+        Self {
+            permits: Permits::new(max),
+            parker: SpinParker,
+        }
+    }
+}
+
+impl<P: Parker> RawSemaphore<P> {
+    /// Create a semaphore admitting at most 'max' threads at a time, parking via 'parker'.
+    ///
+    /// # Panics
+    ///
+    /// Panics if 'max' is zero, see `try_with_parker`.
+    pub fn with_parker(max: usize, parker: P) -> Self {
+        Self::try_with_parker(max, parker).expect("a semaphore needs a capacity of at least one")
+    }
+
+    /// Create a semaphore admitting at most 'max' threads at a time, parking via 'parker'.
+    /// Fails for a 'max' of zero, since such a semaphore could never admit a thread.
+    pub fn try_with_parker(max: usize, parker: P) -> Result<Self, InvalidCapacity> {
+        if max == 0 {
+            return Err(InvalidCapacity);
+        }
+        Ok(Self {
+            permits: Permits::new(max),
+            parker,
+        })
+    }
+
+    /// The count of currently running threads.
+    pub fn current_count(&self) -> usize {
+        self.permits.current()
+    }
+
+    /// The count of threads that could start right now without waiting.
+    pub fn available_permits(&self) -> usize {
+        self.permits.max().saturating_sub(self.permits.current())
+    }
+
+    /// Wait via the parker until a permit is free.
+    /// The returned permit releases the slot again once it is dropped.
+    pub fn wait(&self) -> RawPermit<'_, P> {
+        while !self.permits.try_acquire(1) {
+            self.parker.park();
+        }
+        RawPermit { semaphore: self }
+    }
+
+    /// Acquire a permit only if one is immediately available, never waits.
+    pub fn try_wait(&self) -> Option<RawPermit<'_, P>> {
+        self.permits
+            .try_acquire(1)
+            .then(|| RawPermit { semaphore: self })
+    }
+
+    /// Release a slot, the current count never drops below zero.
+    pub fn release_one(&self) {
+        if self.permits.release(1) > 0 {
+            self.parker.unpark_all();
+        }
+    }
+}
+
+/// A permit acquired from a `RawSemaphore`, released when dropped.
+#[must_use = "the permit is released immediately if it is not held"]
+pub struct RawPermit<'a, P: Parker> {
+    semaphore: &'a RawSemaphore<P>,
+}
+
+impl<P: Parker> Drop for RawPermit<'_, P> {
+    fn drop(&mut self) {
+        self.semaphore.release_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_try_with_parker() {
+        assert!(SpinSemaphore::try_with_parker(0, SpinParker).is_err());
+        assert!(SpinSemaphore::try_with_parker(1, SpinParker).is_ok());
+    }
+
+    #[test]
+    fn test_failed_try_wait_releases_nothing() {
+        let semaphore = SpinSemaphore::new(1);
+        let _permit = semaphore.wait();
+        assert!(semaphore.try_wait().is_none());
+        assert!(semaphore.try_wait().is_none());
+        assert_eq!(semaphore.current_count(), 1);
+    }
+
+    #[test]
+    #[cfg(not(loom))]
+    fn test_new_const_in_static() {
+        static SEMAPHORE: SpinSemaphore = SpinSemaphore::new_const(2);
+
+        let handles: std::vec::Vec<_> = (0..4)
+            .map(|_| std::thread::spawn(|| drop(SEMAPHORE.wait())))
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(SEMAPHORE.available_permits(), 2);
+    }
+
+    #[test]
+    fn test_spin_semaphore_limits_concurrency() {
+        let max = 2;
+        let semaphore = Arc::new(SpinSemaphore::new(max));
+        let running = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+
+        let handles: std::vec::Vec<_> = (0..8)
+            .map(|_| {
+                let semaphore = Arc::clone(&semaphore);
+                let running = Arc::clone(&running);
+                let peak = Arc::clone(&peak);
+                std::thread::spawn(move || {
+                    for _ in 0..100 {
+                        let _permit = semaphore.wait();
+                        let now_running = running.fetch_add(1, Ordering::SeqCst) + 1;
+                        peak.fetch_max(now_running, Ordering::SeqCst);
+                        running.fetch_sub(1, Ordering::SeqCst);
+                    }
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert!(peak.load(Ordering::SeqCst) <= max);
+        assert_eq!(semaphore.current_count(), 0);
+        assert_eq!(semaphore.available_permits(), max);
+    }
+}
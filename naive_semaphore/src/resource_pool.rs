@@ -0,0 +1,132 @@
+use crate::naive_semaphore::{NaiveSemaphore, SemaphorePermit};
+use crate::sync::Mutex;
+use std::ops::{Deref, DerefMut};
+
+/// A pool of reusable objects such as buffers or connections, handing each one to at most
+/// one holder at a time. A `NaiveSemaphore` with one permit per item does the blocking.
+///
+/// ```
+/// use naive_semaphore::ResourcePool;
+///
+/// let pool = ResourcePool::new(vec![String::new()]);
+///
+/// let mut buffer = pool.acquire();
+/// buffer.push_str("reused");
+/// drop(buffer);
+///
+/// assert_eq!(*pool.acquire(), "reused");
+/// ```
+pub struct ResourcePool<T> {
+    semaphore: NaiveSemaphore,
+    // The items not handed out, never empty while a permit is free.
+    items: Mutex<Vec<T>>,
+}
+
+impl<T> ResourcePool<T> {
+    /// Create a pool handing out 'items', admitting as many holders as there are items.
+    ///
+    /// # Panics
+    ///
+    /// Panics if 'items' is empty.
+    pub fn new(items: Vec<T>) -> Self {
+        Self {
+            semaphore: NaiveSemaphore::new(items.len()),
+            items: Mutex::new(items),
+        }
+    }
+
+    /// The count of items currently not handed out.
+    pub fn available(&self) -> usize {
+        self.semaphore.available_permits()
+    }
+
+    /// Block a thread until an item is free and take it.
+    /// The returned guard puts the item back into the pool once it is dropped.
+    pub fn acquire(&self) -> PooledItem<'_, T> {
+        let permit = self.semaphore.wait();
+        // the permit guarantees an item left, at most 'max' are handed out
+        let item = self
+            .items
+            .lock()
+            .pop()
+            .expect("a permit without a pooled item");
+        PooledItem {
+            pool: self,
+            item: Some(item),
+            _permit: permit,
+        }
+    }
+}
+
+/// An item taken from a `ResourcePool`, returned to the pool when dropped (also on panic).
+#[must_use = "the item is returned immediately if it is not held"]
+pub struct PooledItem<'a, T> {
+    pool: &'a ResourcePool<T>,
+    // Only `None` while dropping.
+    item: Option<T>,
+    _permit: SemaphorePermit<'a>,
+}
+
+impl<T> Deref for PooledItem<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.item.as_ref().unwrap()
+    }
+}
+
+impl<T> DerefMut for PooledItem<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.item.as_mut().unwrap()
+    }
+}
+
+impl<T> Drop for PooledItem<'_, T> {
+    fn drop(&mut self) {
+        // return the item first, the permit itself is released right after when dropping the field
+        if let Some(item) = self.item.take() {
+            self.pool.items.lock().push(item);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[test]
+    fn test_items_never_shared() {
+        let buffers = (0..3).map(|idx| format!("buffer-{}", idx)).collect();
+        let pool = Arc::new(ResourcePool::<String>::new(buffers));
+
+        let handles: Vec<_> = (0..12)
+            .map(|idx| {
+                let pool = Arc::clone(&pool);
+                std::thread::spawn(move || {
+                    for _ in 0..20 {
+                        let mut buffer = pool.acquire();
+                        // a second holder would find someone else's marker in the buffer
+                        assert!(!buffer.contains('#'));
+                        let marker = format!("#{}#", idx);
+                        buffer.push_str(&marker);
+                        std::thread::sleep(Duration::from_micros(200));
+                        assert!(buffer.ends_with(&marker));
+                        let len = buffer.len() - marker.len();
+                        buffer.truncate(len);
+                    }
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(pool.available(), 3);
+
+        let mut names: Vec<_> = pool.items.lock().clone();
+        names.sort();
+        assert_eq!(names, ["buffer-0", "buffer-1", "buffer-2"]);
+    }
+}
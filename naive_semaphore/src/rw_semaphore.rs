@@ -0,0 +1,288 @@
+use crate::error::InvalidCapacity;
+use crate::sync::{Condvar, Mutex, MutexGuard};
+
+struct Holders {
+    // The count of threads holding a shared permit.
+    shared: usize,
+    // Whether a thread holds the exclusive permit.
+    exclusive: bool,
+    // The count of threads waiting for the exclusive permit, new shared holders wait for them.
+    pending_writers: usize,
+}
+
+/// A semaphore admitting either up to 'max' shared holders or a single exclusive holder,
+/// e.g. for a resource allowing many concurrent readers but only one writer.
+///
+/// Writers take priority: once a thread waits in `acquire_exclusive`, no new shared holders
+/// are admitted, so a steady stream of readers cannot starve it.
+///
+/// ```
+/// use naive_semaphore::RwSemaphore;
+///
+/// let semaphore = RwSemaphore::new(2);
+///
+/// let reader = semaphore.acquire_shared();
+/// assert!(semaphore.try_acquire_exclusive().is_none());
+///
+/// drop(reader);
+/// assert!(semaphore.try_acquire_exclusive().is_some());
+/// ```
+pub struct RwSemaphore {
+    max: usize,
+    holders: Mutex<Holders>,
+    waiter: Condvar,
+}
+
+impl RwSemaphore {
+    /// Create a semaphore admitting up to 'max' shared holders at a time.
+    ///
+    /// # Panics
+    ///
+    /// Panics if 'max' is zero, see `try_new`.
+    pub fn new(max: usize) -> Self {
+        Self::try_new(max).expect("a semaphore needs a capacity of at least one")
+    }
+
+    /// Create a semaphore admitting up to 'max' shared holders at a time.
+    /// Fails for a 'max' of zero, since such a semaphore could never admit a reader.
+    pub fn try_new(max: usize) -> Result<Self, InvalidCapacity> {
+        if max == 0 {
+            return Err(InvalidCapacity);
+        }
+        Ok(Self {
+            max,
+            holders: Mutex::new(Holders {
+                shared: 0,
+                exclusive: false,
+                pending_writers: 0,
+            }),
+            waiter: Condvar::new(),
+        })
+    }
+
+    /// The count of threads currently holding a shared permit.
+    pub fn shared_count(&self) -> usize {
+        self.lock().shared
+    }
+
+    /// Whether a thread currently holds the exclusive permit.
+    pub fn is_exclusive(&self) -> bool {
+        self.lock().exclusive
+    }
+
+    /// Block a thread until a shared permit is free and no writer holds or waits for the
+    /// exclusive one. The returned permit is released again once it is dropped.
+    pub fn acquire_shared(&self) -> SharedPermit<'_> {
+        let mut holders = self.lock();
+        while !self.admits_shared(&holders) {
+            holders = self.waiter.wait(holders);
+        }
+        holders.shared += 1;
+        SharedPermit { semaphore: self }
+    }
+
+    /// Acquire a shared permit only if `acquire_shared` would not block, never blocks.
+    pub fn try_acquire_shared(&self) -> Option<SharedPermit<'_>> {
+        let mut holders = self.lock();
+        if !self.admits_shared(&holders) {
+            return None;
+        }
+        holders.shared += 1;
+        Some(SharedPermit { semaphore: self })
+    }
+
+    /// Block a thread until all shared holders drained and take the exclusive permit.
+    /// The returned permit is released again once it is dropped.
+    pub fn acquire_exclusive(&self) -> ExclusivePermit<'_> {
+        let mut holders = self.lock();
+        holders.pending_writers += 1;
+        while holders.exclusive || holders.shared > 0 {
+            holders = self.waiter.wait(holders);
+        }
+        holders.pending_writers -= 1;
+        holders.exclusive = true;
+        ExclusivePermit { semaphore: self }
+    }
+
+    /// Acquire the exclusive permit only if nobody holds a permit, never blocks.
+    pub fn try_acquire_exclusive(&self) -> Option<ExclusivePermit<'_>> {
+        let mut holders = self.lock();
+        if holders.exclusive || holders.shared > 0 {
+            return None;
+        }
+        holders.exclusive = true;
+        Some(ExclusivePermit { semaphore: self })
+    }
+
+    fn admits_shared(&self, holders: &Holders) -> bool {
+        !holders.exclusive && holders.pending_writers == 0 && holders.shared < self.max
+    }
+
+    fn release_shared(&self) {
+        let mut holders = self.lock();
+        holders.shared -= 1;
+        // both a waiting writer and waiting readers may fit now
+        self.waiter.notify_all();
+    }
+
+    fn downgrade(&self) {
+        let mut holders = self.lock();
+        holders.exclusive = false;
+        holders.shared += 1;
+        // waiting readers may join, unless a writer is waiting as well
+        self.waiter.notify_all();
+    }
+
+    fn release_exclusive(&self) {
+        let mut holders = self.lock();
+        holders.exclusive = false;
+        self.waiter.notify_all();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Holders> {
+        self.holders.lock()
+    }
+}
+
+/// A shared permit acquired from a `RwSemaphore`, released when dropped (also on panic).
+#[must_use = "the permit is released immediately if it is not held"]
+pub struct SharedPermit<'a> {
+    semaphore: &'a RwSemaphore,
+}
+
+impl Drop for SharedPermit<'_> {
+    fn drop(&mut self) {
+        self.semaphore.release_shared();
+    }
+}
+
+/// The exclusive permit acquired from a `RwSemaphore`, released when dropped (also on panic).
+#[must_use = "the permit is released immediately if it is not held"]
+pub struct ExclusivePermit<'a> {
+    semaphore: &'a RwSemaphore,
+}
+
+impl<'a> ExclusivePermit<'a> {
+    /// Turn the exclusive permit into a shared one in a single step, admitting other readers
+    /// again without giving a writer the chance to get in between.
+    pub fn downgrade(self) -> SharedPermit<'a> {
+        let semaphore = self.semaphore;
+        // the exclusive permit is converted, not released
+        std::mem::forget(self);
+        semaphore.downgrade();
+        SharedPermit { semaphore }
+    }
+}
+
+impl Drop for ExclusivePermit<'_> {
+    fn drop(&mut self) {
+        self.semaphore.release_exclusive();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Barrier};
+    use std::time::Duration;
+
+    #[test]
+    fn test_many_readers() {
+        let max = 4;
+        let semaphore = Arc::new(RwSemaphore::new(max));
+        // every reader holds its permit until all of them got in
+        let barrier = Arc::new(Barrier::new(max));
+
+        let handles: Vec<_> = (0..max)
+            .map(|_| {
+                let semaphore = Arc::clone(&semaphore);
+                let barrier = Arc::clone(&barrier);
+                std::thread::spawn(move || {
+                    let _permit = semaphore.acquire_shared();
+                    barrier.wait();
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(semaphore.shared_count(), 0);
+
+        let held: Vec<_> = (0..max).map(|_| semaphore.acquire_shared()).collect();
+        assert!(semaphore.try_acquire_shared().is_none());
+        drop(held);
+    }
+
+    #[test]
+    fn test_writer_waits_for_readers() {
+        let semaphore = Arc::new(RwSemaphore::new(2));
+        let reader = semaphore.acquire_shared();
+
+        let sem = Arc::clone(&semaphore);
+        let writer = std::thread::spawn(move || {
+            let _permit = sem.acquire_exclusive();
+        });
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(!writer.is_finished());
+        assert!(!semaphore.is_exclusive());
+
+        drop(reader);
+        writer.join().unwrap();
+        assert_eq!(semaphore.shared_count(), 0);
+        assert!(!semaphore.is_exclusive());
+    }
+
+    #[test]
+    fn test_downgrade() {
+        let semaphore = Arc::new(RwSemaphore::new(2));
+        let writer = semaphore.acquire_exclusive();
+        assert!(semaphore.try_acquire_shared().is_none());
+
+        let reader = writer.downgrade();
+        assert!(!semaphore.is_exclusive());
+        assert_eq!(semaphore.shared_count(), 1);
+
+        // another reader gets in right away, a writer has to wait for both
+        let sem = Arc::clone(&semaphore);
+        std::thread::spawn(move || drop(sem.acquire_shared()))
+            .join()
+            .unwrap();
+        assert!(semaphore.try_acquire_exclusive().is_none());
+        let sem = Arc::clone(&semaphore);
+        let next_writer = std::thread::spawn(move || drop(sem.acquire_exclusive()));
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(!next_writer.is_finished());
+
+        drop(reader);
+        next_writer.join().unwrap();
+        assert_eq!(semaphore.shared_count(), 0);
+        assert!(!semaphore.is_exclusive());
+    }
+
+    #[test]
+    fn test_pending_writer_blocks_new_readers() {
+        let semaphore = Arc::new(RwSemaphore::new(4));
+        let reader = semaphore.acquire_shared();
+
+        let sem = Arc::clone(&semaphore);
+        let writer = std::thread::spawn(move || {
+            let _permit = sem.acquire_exclusive();
+        });
+        std::thread::sleep(Duration::from_millis(20));
+
+        // there is room for more readers, but the waiting writer comes first
+        assert!(semaphore.try_acquire_shared().is_none());
+        let sem = Arc::clone(&semaphore);
+        let late_reader = std::thread::spawn(move || {
+            let _permit = sem.acquire_shared();
+        });
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(!late_reader.is_finished());
+
+        drop(reader);
+        writer.join().unwrap();
+        late_reader.join().unwrap();
+        assert_eq!(semaphore.shared_count(), 0);
+    }
+}
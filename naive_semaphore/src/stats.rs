@@ -0,0 +1,149 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// A snapshot of the cumulative statistics of a `NaiveSemaphore`, see `NaiveSemaphore::stats`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SemaphoreStats {
+    /// The count of successful acquisitions, each counted once regardless of its permits.
+    pub total_acquisitions: u64,
+    /// The count of calls that had to park waiting for a permit, including timed out ones.
+    pub blocked_count: u64,
+    /// The time spent parked, summed over all blocked calls.
+    pub total_blocked: Duration,
+}
+
+/// Collects the numbers behind `SemaphoreStats`, updated lock-free.
+#[derive(Default)]
+pub(crate) struct StatsRecorder {
+    acquisitions: AtomicU64,
+    blocked: AtomicU64,
+    blocked_nanos: AtomicU64,
+}
+
+impl StatsRecorder {
+    pub fn record_acquisition(&self) {
+        self.acquisitions.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_blocked(&self, blocked: Duration) {
+        let nanos = u64::try_from(blocked.as_nanos()).unwrap_or(u64::MAX);
+        self.blocked.fetch_add(1, Ordering::Relaxed);
+        self.blocked_nanos.fetch_add(nanos, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> SemaphoreStats {
+        SemaphoreStats {
+            total_acquisitions: self.acquisitions.load(Ordering::Relaxed),
+            blocked_count: self.blocked.load(Ordering::Relaxed),
+            total_blocked: Duration::from_nanos(self.blocked_nanos.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+/// Sub-buckets per power of two of `WaitHistogram`, bounding the error of a percentile to an
+/// eighth of it.
+const SUB_BUCKETS: usize = 8;
+const SUB_BITS: u32 = SUB_BUCKETS.trailing_zeros();
+
+/// A histogram of the waits of all acquisitions in nanoseconds, updated lock-free. Values
+/// below `SUB_BUCKETS` nanoseconds get a bucket each, every power of two above is split into
+/// `SUB_BUCKETS` equally wide buckets.
+pub(crate) struct WaitHistogram {
+    buckets: Vec<AtomicU64>,
+}
+
+impl WaitHistogram {
+    pub fn new() -> Self {
+        let buckets = Self::index(u64::MAX) + 1;
+        Self {
+            buckets: (0..buckets).map(|_| AtomicU64::new(0)).collect(),
+        }
+    }
+
+    fn index(nanos: u64) -> usize {
+        if nanos < SUB_BUCKETS as u64 {
+            return nanos as usize;
+        }
+        let shift = 63 - nanos.leading_zeros() - SUB_BITS;
+        let sub = (nanos >> shift) as usize & (SUB_BUCKETS - 1);
+        SUB_BUCKETS + shift as usize * SUB_BUCKETS + sub
+    }
+
+    /// The largest value falling into the bucket at 'index'.
+    fn upper_bound(index: usize) -> u64 {
+        if index < SUB_BUCKETS {
+            return index as u64;
+        }
+        let shift = (index - SUB_BUCKETS) / SUB_BUCKETS;
+        let sub = (index % SUB_BUCKETS) as u64;
+        ((SUB_BUCKETS as u64 + sub + 1) << shift) - 1
+    }
+
+    pub fn record(&self, waited: Duration) {
+        let nanos = u64::try_from(waited.as_nanos()).unwrap_or(u64::MAX);
+        self.buckets[Self::index(nanos)].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// The wait not exceeded by 'percentile' percent of the acquisitions, rounded up to the
+    /// bucket it falls into, `None` before the first acquisition.
+    pub fn percentile(&self, percentile: f64) -> Option<Duration> {
+        let counts: Vec<u64> = self
+            .buckets
+            .iter()
+            .map(|bucket| bucket.load(Ordering::Relaxed))
+            .collect();
+        let total: u64 = counts.iter().sum();
+        if total == 0 {
+            return None;
+        }
+        let rank = ((percentile / 100.0 * total as f64).ceil() as u64).clamp(1, total);
+        let mut seen = 0;
+        let index = counts
+            .iter()
+            .position(|&count| {
+                seen += count;
+                seen >= rank
+            })
+            .unwrap_or(counts.len() - 1);
+        // the last bucket overflows past `u64::MAX` nanoseconds
+        Some(Duration::from_nanos(if index + 1 == counts.len() {
+            u64::MAX
+        } else {
+            Self::upper_bound(index)
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram_buckets() {
+        for nanos in [0, 1, 7, 8, 9, 15, 16, 17, 1_000, 123_456_789, u64::MAX / 3] {
+            let index = WaitHistogram::index(nanos);
+            assert!(nanos <= WaitHistogram::upper_bound(index), "{}", nanos);
+            if index > 0 {
+                assert!(nanos > WaitHistogram::upper_bound(index - 1), "{}", nanos);
+            }
+        }
+    }
+
+    #[test]
+    fn test_histogram_percentiles() {
+        let histogram = WaitHistogram::new();
+        assert_eq!(histogram.percentile(50.0), None);
+        for _ in 0..90 {
+            histogram.record(Duration::from_micros(1));
+        }
+        for _ in 0..10 {
+            histogram.record(Duration::from_millis(10));
+        }
+
+        let p50 = histogram.percentile(50.0).unwrap();
+        let p99 = histogram.percentile(99.0).unwrap();
+        assert!((Duration::from_micros(1)..Duration::from_nanos(1_125)).contains(&p50));
+        assert!((Duration::from_millis(10)..Duration::from_micros(11_250)).contains(&p99));
+        assert_eq!(histogram.percentile(0.0), histogram.percentile(1.0));
+    }
+}
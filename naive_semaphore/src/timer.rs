@@ -0,0 +1,145 @@
+//! The timer behind `NaiveSemaphore::acquire_timeout`: a single background thread waking
+//! tasks at their deadlines, so that timeouts work on any executor without its own timer.
+
+use crate::sync::{Condvar, Mutex};
+use std::collections::BTreeMap;
+use std::sync::OnceLock;
+use std::task::Waker;
+use std::time::Instant;
+
+/// The pending deadlines, earliest first. The id tells apart equal deadlines.
+#[derive(Default)]
+struct Deadlines {
+    next_id: u64,
+    wakers: BTreeMap<(Instant, u64), Waker>,
+}
+
+struct Timer {
+    deadlines: Mutex<Deadlines>,
+    // Notified whenever an earlier deadline may have been registered.
+    changed: Condvar,
+}
+
+impl Timer {
+    /// The timer shared by the whole process, its thread started on first use.
+    fn get() -> &'static Timer {
+        static TIMER: OnceLock<&'static Timer> = OnceLock::new();
+        TIMER.get_or_init(|| {
+            let timer: &'static Timer = Box::leak(Box::new(Timer {
+                deadlines: Mutex::new(Deadlines::default()),
+                changed: Condvar::new(),
+            }));
+            std::thread::Builder::new()
+                .name("naive_semaphore-timer".to_string())
+                .spawn(move || timer.run())
+                .expect("failed to spawn the timer thread");
+            timer
+        })
+    }
+
+    /// Wake every task whose deadline passed, then sleep until the next one.
+    fn run(&self) {
+        let mut deadlines = self.deadlines.lock();
+        loop {
+            let now = Instant::now();
+            let pending = deadlines.wakers.split_off(&(now, u64::MAX));
+            let due = std::mem::replace(&mut deadlines.wakers, pending);
+            if !due.is_empty() {
+                // the tasks may register again right away
+                drop(deadlines);
+                due.into_values().for_each(Waker::wake);
+                deadlines = self.deadlines.lock();
+                continue;
+            }
+            deadlines = match deadlines.wakers.keys().next() {
+                Some(&(deadline, _)) => self.changed.wait_timeout(deadlines, deadline - now),
+                None => self.changed.wait(deadlines),
+            };
+        }
+    }
+}
+
+/// A deadline registered with the timer, cancelled when dropped.
+pub(crate) struct TimerEntry {
+    key: (Instant, u64),
+}
+
+impl TimerEntry {
+    /// Wake 'waker' once 'deadline' passed.
+    pub fn register(deadline: Instant, waker: &Waker) -> Self {
+        let timer = Timer::get();
+        let mut deadlines = timer.deadlines.lock();
+        let key = (deadline, deadlines.next_id);
+        deadlines.next_id += 1;
+        deadlines.wakers.insert(key, waker.clone());
+        drop(deadlines);
+        timer.changed.notify_one();
+        Self { key }
+    }
+
+    /// Wake 'waker' instead of the one registered before, if the deadline did not pass yet.
+    pub fn update(&self, waker: &Waker) {
+        if let Some(registered) = Timer::get().deadlines.lock().wakers.get_mut(&self.key) {
+            if !registered.will_wake(waker) {
+                *registered = waker.clone();
+            }
+        }
+    }
+}
+
+impl Drop for TimerEntry {
+    fn drop(&mut self) {
+        Timer::get().deadlines.lock().wakers.remove(&self.key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::task::Wake;
+    use std::time::Duration;
+
+    #[derive(Default)]
+    struct CountingWaker(AtomicUsize);
+
+    impl Wake for CountingWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn test_deadline_wakes_once() {
+        let counting = Arc::new(CountingWaker::default());
+        let waker = Waker::from(Arc::clone(&counting));
+        let entry = TimerEntry::register(Instant::now() + Duration::from_millis(10), &waker);
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while counting.0.load(Ordering::SeqCst) == 0 {
+            assert!(Instant::now() < deadline, "the deadline never fired");
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        assert!(!Timer::get()
+            .deadlines
+            .lock()
+            .wakers
+            .contains_key(&entry.key));
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(counting.0.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_dropped_entry_never_wakes() {
+        let counting = Arc::new(CountingWaker::default());
+        let waker = Waker::from(Arc::clone(&counting));
+        let entry = TimerEntry::register(Instant::now() + Duration::from_millis(10), &waker);
+        let key = entry.key;
+
+        drop(entry);
+        assert!(!Timer::get().deadlines.lock().wakers.contains_key(&key));
+        std::thread::sleep(Duration::from_millis(30));
+        assert_eq!(counting.0.load(Ordering::SeqCst), 0);
+    }
+}